@@ -0,0 +1,72 @@
+//! Compares searching a large tree on one thread and on several.
+//!
+//! cargo bench --bench parallel
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+use std::time::Instant;
+
+use io_project::search_paths;
+use io_project::Config;
+
+const DIRS: usize = 20;
+const FILES_PER_DIR: usize = 20;
+const LINES_PER_FILE: usize = 5_000;
+const RUNS: u32 = 3;
+
+fn main() {
+  let root = env::temp_dir().join(format!("minigrep_bench_{}", std::process::id()));
+  generate(&root);
+
+  let args: Vec<String> = ["minigrep", "--regex", "-i", r"fr[aeiou]g \d+7$", root.to_str().unwrap()]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect();
+  let mut config = Config::new(&args).unwrap();
+
+  // Warms the page cache so the first run isn't penalized
+  search_paths(&config, &config.output, io::sink()).unwrap();
+
+  let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+  println!("{} files, {} lines each, {} CPUs", DIRS * FILES_PER_DIR, LINES_PER_FILE, cpus);
+  let mut counts = vec![1, 2, 4, cpus];
+  counts.sort_unstable();
+  counts.dedup();
+
+  let mut single = Duration::ZERO;
+  for threads in counts {
+    config.threads = threads;
+    let start = Instant::now();
+    for _ in 0..RUNS {
+      search_paths(&config, &config.output, io::sink()).unwrap();
+    }
+    let elapsed = start.elapsed() / RUNS;
+    if threads == 1 {
+      single = elapsed;
+    }
+    println!(
+      "{:>3} thread(s): {:>10.2?}  {:.2}x",
+      threads,
+      elapsed,
+      single.as_secs_f64() / elapsed.as_secs_f64()
+    );
+  }
+
+  fs::remove_dir_all(root).unwrap();
+}
+
+fn generate(root: &Path) {
+  for dir in 0..DIRS {
+    let dir_path = root.join(format!("dir{}", dir));
+    fs::create_dir_all(&dir_path).unwrap();
+    for file in 0..FILES_PER_DIR {
+      let contents: String = (0..LINES_PER_FILE)
+        .map(|line| format!("{} How public, like a frog {}\n", file, line))
+        .collect();
+      fs::write(dir_path.join(format!("poem{}.txt", file)), contents).unwrap();
+    }
+  }
+}
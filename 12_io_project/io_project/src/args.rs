@@ -0,0 +1,231 @@
+use std::error::Error;
+use std::fmt;
+
+/// A command line option, `-s` and `--long` are two spellings of the same one.
+#[derive(Debug, PartialEq)]
+pub struct Opt {
+  pub short: Option<char>,
+  pub long: &'static str,
+  /// The name of the value the option takes, if it takes one
+  pub value: Option<&'static str>,
+  pub help: &'static str,
+}
+
+pub const OPTIONS: &[Opt] = &[
+  Opt { short: Some('e'), long: "regexp", value: Some("PATTERN"), help: "Search for PATTERN, can be repeated" },
+  Opt { short: Some('i'), long: "ignore-case", value: None, help: "Ignore case, also set by CASE_INSENSITIVE" },
+  Opt { short: Some('S'), long: "smart-case", value: None, help: "Ignore case unless the pattern has uppercase letters" },
+  Opt { short: None, long: "regex", value: None, help: "Read patterns as regular expressions" },
+  Opt { short: Some('w'), long: "word-regexp", value: None, help: "Only match whole words" },
+  Opt { short: Some('v'), long: "invert-match", value: None, help: "Select the lines that don't match" },
+  Opt { short: Some('n'), long: "line-number", value: None, help: "Print line numbers" },
+  Opt { short: Some('b'), long: "byte-offset", value: None, help: "Print the byte offset of each line" },
+  Opt { short: None, long: "json", value: None, help: "Print the lines as JSON objects, one per line" },
+  Opt { short: Some('c'), long: "count", value: None, help: "Only print how many lines match in each file" },
+  Opt { short: Some('l'), long: "files-with-matches", value: None, help: "Only print the paths of the files that match" },
+  Opt { short: Some('A'), long: "after-context", value: Some("NUM"), help: "Print NUM lines after each match" },
+  Opt { short: Some('B'), long: "before-context", value: Some("NUM"), help: "Print NUM lines before each match" },
+  Opt { short: Some('C'), long: "context", value: Some("NUM"), help: "Print NUM lines around each match" },
+  Opt { short: Some('r'), long: "replace", value: Some("TEXT"), help: "Print the lines with the matches replaced by TEXT" },
+  Opt { short: None, long: "write", value: None, help: "Edit the files with the replacements" },
+  Opt { short: None, long: "dry-run", value: None, help: "Print the diff --write would apply" },
+  Opt { short: Some('j'), long: "threads", value: Some("NUM"), help: "Search NUM files at once, one per CPU by default" },
+  Opt { short: None, long: "include", value: Some("GLOB"), help: "Only search the files matching GLOB" },
+  Opt { short: None, long: "exclude", value: Some("GLOB"), help: "Skip the files and directories matching GLOB" },
+  Opt { short: Some('h'), long: "help", value: None, help: "Print this help" },
+  Opt { short: Some('V'), long: "version", value: None, help: "Print the version" },
+];
+
+/// Why the command line can't be turned into a config.
+///
+/// `--help` and `--version` stop the parsing too, their message is what
+/// should be printed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+  MissingPattern,
+  UnknownOption(String),
+  /// The option as it was written
+  MissingValue(String),
+  /// The option doesn't take a value but `--option=value` gave one
+  UnexpectedValue(String),
+  InvalidValue { option: String, value: String },
+  /// The option only makes sense with `--replace`
+  MissingReplace(String),
+  HelpRequested,
+  VersionRequested,
+}
+
+impl ConfigError {
+  /// Whether this is `--help` or `--version` rather than a mistake.
+  pub fn is_request(&self) -> bool {
+    matches!(self, ConfigError::HelpRequested | ConfigError::VersionRequested)
+  }
+}
+
+impl fmt::Display for ConfigError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ConfigError::MissingPattern => write!(f, "no pattern to search for"),
+      ConfigError::UnknownOption(option) => write!(f, "unknown option {}", option),
+      ConfigError::MissingValue(option) => write!(f, "{} needs a value", option),
+      ConfigError::UnexpectedValue(option) => write!(f, "{} doesn't take a value", option),
+      ConfigError::InvalidValue { option, value } => write!(f, "invalid value for {}: {:?}", option, value),
+      ConfigError::MissingReplace(option) => write!(f, "{} needs --replace", option),
+      ConfigError::HelpRequested => write!(f, "{}", usage()),
+      ConfigError::VersionRequested => write!(f, "minigrep {}", env!("CARGO_PKG_VERSION")),
+    }
+  }
+}
+
+impl Error for ConfigError {}
+
+/// An argument of the command line, with the option it stands for.
+#[derive(Debug, PartialEq)]
+pub enum Arg {
+  /// The option, how it was written and its value
+  Option(&'static Opt, String, Option<String>),
+  Positional(String),
+}
+
+/// Splits the arguments, without the program name, into options and
+/// positional arguments.
+///
+/// Short options can be grouped as in `-inC2`, values follow their option
+/// directly or as the next argument, `--long=value` works too. Everything
+/// after `--` is positional, and so is `-` on its own.
+pub fn parse(args: &[String]) -> Result<Vec<Arg>, ConfigError> {
+  let mut parsed = Vec::new();
+  let mut args = args.iter();
+  while let Some(arg) = args.next() {
+    if arg == "--" {
+      parsed.extend(args.by_ref().map(|arg| Arg::Positional(arg.clone())));
+    } else if let Some(long) = arg.strip_prefix("--") {
+      let (name, value) = match long.split_once('=') {
+        Some((name, value)) => (name, Some(value.to_string())),
+        None => (long, None),
+      };
+      let opt = OPTIONS
+        .iter()
+        .find(|opt| opt.long == name)
+        .ok_or_else(|| ConfigError::UnknownOption(format!("--{}", name)))?;
+      let written = format!("--{}", name);
+      let value = match (opt.value, value) {
+        (Some(_), None) => Some(args.next().ok_or_else(|| ConfigError::MissingValue(written.clone()))?.clone()),
+        (None, Some(_)) => return Err(ConfigError::UnexpectedValue(written)),
+        (_, value) => value,
+      };
+      parsed.push(Arg::Option(opt, written, value));
+    } else if arg.len() > 1 && arg.starts_with('-') {
+      let shorts = &arg[1..];
+      for (i, c) in shorts.char_indices() {
+        let written = format!("-{}", c);
+        let opt = OPTIONS
+          .iter()
+          .find(|opt| opt.short == Some(c))
+          .ok_or_else(|| ConfigError::UnknownOption(written.clone()))?;
+        if opt.value.is_none() {
+          parsed.push(Arg::Option(opt, written, None));
+          continue;
+        }
+        // The rest of the group is the value, or the next argument is
+        let rest = &shorts[i + c.len_utf8()..];
+        let value = if rest.is_empty() {
+          args.next().ok_or_else(|| ConfigError::MissingValue(written.clone()))?.clone()
+        } else {
+          rest.to_string()
+        };
+        parsed.push(Arg::Option(opt, written, Some(value)));
+        break;
+      }
+    } else {
+      parsed.push(Arg::Positional(arg.clone()));
+    }
+  }
+  Ok(parsed)
+}
+
+/// The help text, listing every option.
+pub fn usage() -> String {
+  let names: Vec<String> = OPTIONS
+    .iter()
+    .map(|opt| {
+      let short = opt.short.map_or(String::from("    "), |c| format!("-{}, ", c));
+      let value = opt.value.map_or(String::new(), |value| format!(" {}", value));
+      format!("{}--{}{}", short, opt.long, value)
+    })
+    .collect();
+  let width = names.iter().map(|name| name.len()).max().unwrap_or(0);
+
+  let mut usage = String::from(
+    "\
+Usage: minigrep [OPTION]... PATTERN [PATH]...
+Searches for PATTERN in each PATH, going through directories recursively.
+Without a PATH, or with -, standard input is searched.
+
+Options:",
+  );
+  for (name, opt) in names.iter().zip(OPTIONS) {
+    usage.push_str(&format!("\n  {:width$}  {}", name, opt.help, width = width));
+  }
+  usage
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse_args(args: &[&str]) -> Result<Vec<(String, Option<String>)>, ConfigError> {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    Ok(
+      parse(&args)?
+        .into_iter()
+        .map(|arg| match arg {
+          Arg::Option(opt, _, value) => (opt.long.to_string(), value),
+          Arg::Positional(arg) => (arg, None),
+        })
+        .collect(),
+    )
+  }
+
+  fn arg(name: &str, value: Option<&str>) -> (String, Option<String>) {
+    (name.to_string(), value.map(String::from))
+  }
+
+  #[test]
+  fn short_and_long_options() {
+    assert_eq!(
+      vec![
+        arg("ignore-case", None),
+        arg("line-number", None),
+        arg("context", Some("2")),
+        arg("after-context", Some("3")),
+        arg("include", Some("*.rs")),
+        arg("regexp", Some("frog")),
+        arg("-", None),
+      ],
+      parse_args(&["-inC2", "-A", "3", "--include=*.rs", "--regexp", "frog", "-"]).unwrap()
+    );
+    assert_eq!(
+      vec![arg("invert-match", None), arg("-n", None), arg("--regex", None)],
+      parse_args(&["-v", "--", "-n", "--regex"]).unwrap()
+    );
+  }
+
+  #[test]
+  fn errors() {
+    assert_eq!(Err(ConfigError::UnknownOption(String::from("-x"))), parse_args(&["-nx"]));
+    assert_eq!(Err(ConfigError::UnknownOption(String::from("--frog"))), parse_args(&["--frog"]));
+    assert_eq!(Err(ConfigError::MissingValue(String::from("-A"))), parse_args(&["-nA"]));
+    assert_eq!(Err(ConfigError::MissingValue(String::from("--exclude"))), parse_args(&["--exclude"]));
+    assert_eq!(Err(ConfigError::UnexpectedValue(String::from("--regex"))), parse_args(&["--regex=yes"]));
+    assert_eq!("-e needs a value", ConfigError::MissingValue(String::from("-e")).to_string());
+  }
+
+  #[test]
+  fn usage_lists_options() {
+    let usage = usage();
+    assert!(usage.starts_with("Usage: minigrep [OPTION]... PATTERN [PATH]..."));
+    assert!(usage.contains("\n  -e, --regexp PATTERN      Search for PATTERN, can be repeated\n"));
+    assert!(usage.contains("\n      --regex               Read patterns as regular expressions\n"));
+  }
+}
@@ -1,65 +1,457 @@
 use std::error::Error;
-use std::fs;
 use std::env;
+use std::io;
+use std::io::IsTerminal;
+use std::io::Write;
+use std::ops::Range;
+use std::path::Path;
+use std::path::PathBuf;
+
+pub mod args;
+pub mod lines;
+pub mod matcher;
+pub mod output;
+pub mod pool;
+pub mod replace;
+pub mod walk;
+
+use args::Arg;
+pub use args::ConfigError;
+use matcher::AnyOf;
+use matcher::Contains;
+use matcher::ContainsIgnoreCase;
+use matcher::Invert;
+use matcher::Matcher;
+use matcher::RegexMatcher;
+use matcher::WholeWord;
+use output::Format;
+use output::PrintOptions;
+use output::Printer;
+use walk::Filter;
+use walk::Walk;
+
+/// The path reading standard input
+pub const STDIN: &str = "-";
 
 pub struct Config {
-  pub query: String,
-  pub filename: String,
+  pub patterns: Vec<String>,
+  /// Files, directories searched recursively, or `-` for standard input
+  pub paths: Vec<String>,
   pub case_sensitive: bool,
+  /// Each pattern is case sensitive only if it has an uppercase letter
+  pub smart_case: bool,
+  pub regex: bool,
+  pub whole_word: bool,
+  pub invert: bool,
+  pub include: Vec<String>,
+  pub exclude: Vec<String>,
+  pub output: PrintOptions,
+  /// How many files are searched at once, 0 for one per CPU
+  pub threads: usize,
+  /// What the matches are replaced with
+  pub replace: Option<String>,
+  /// Edits the files with the replacements instead of printing
+  pub write: bool,
+  /// Prints the diff the replacements would make to the files
+  pub dry_run: bool,
 }
 
 impl Config {
-  pub fn new(args: &[String]) -> Result<Config, &'static str> {
-    if args.len() < 3 {
-      return Err("not enough arguments");
+  /// Parses the command line, see `args::usage` for the options.
+  ///
+  /// The pattern is the first positional argument unless `-e` gives some.
+  /// Without `-i` or `-S`, setting `CASE_INSENSITIVE` ignores case too.
+  pub fn new(args: &[String]) -> Result<Config, ConfigError> {
+    let mut patterns = Vec::new();
+    let mut positional = Vec::new();
+    let mut ignore_case = false;
+    let mut smart_case = false;
+    let mut regex = false;
+    let mut whole_word = false;
+    let mut invert = false;
+    let mut include = Vec::new();
+    let mut exclude = Vec::new();
+    let mut output = PrintOptions::default();
+    let mut threads = 0;
+    let mut replace = None;
+    let mut write = false;
+    let mut dry_run = false;
+
+    for arg in args::parse(args.get(1..).unwrap_or(&[]))? {
+      let (opt, written, value) = match arg {
+        Arg::Option(opt, written, value) => (opt, written, value.unwrap_or_default()),
+        Arg::Positional(arg) => {
+          positional.push(arg);
+          continue;
+        }
+      };
+      match opt.long {
+        "regexp" => patterns.push(value),
+        // The last of the two wins
+        "ignore-case" => (ignore_case, smart_case) = (true, false),
+        "smart-case" => (ignore_case, smart_case) = (false, true),
+        "regex" => regex = true,
+        "word-regexp" => whole_word = true,
+        "invert-match" => invert = true,
+        "line-number" => output.line_number = true,
+        "byte-offset" => output.byte_offset = true,
+        "json" => output.format = Format::Json,
+        "count" => output.format = Format::Count,
+        "files-with-matches" => output.format = Format::FilesWithMatches,
+        "after-context" => output.after = number(written, value)?,
+        "before-context" => output.before = number(written, value)?,
+        "context" => {
+          output.before = number(written, value)?;
+          output.after = output.before;
+        }
+        "threads" => threads = number(written, value)?,
+        "replace" => replace = Some(value),
+        "write" => write = true,
+        "dry-run" => dry_run = true,
+        "include" => include.push(value),
+        "exclude" => exclude.push(value),
+        "help" => return Err(ConfigError::HelpRequested),
+        "version" => return Err(ConfigError::VersionRequested),
+        _ => unreachable!("option --{} isn't handled", opt.long),
+      }
+    }
+
+    if replace.is_none() && (write || dry_run) {
+      let option = if write { "--write" } else { "--dry-run" };
+      return Err(ConfigError::MissingReplace(String::from(option)));
+    }
+
+    let mut positional = positional.into_iter();
+    if patterns.is_empty() {
+      patterns.push(positional.next().ok_or(ConfigError::MissingPattern)?);
+    }
+    let mut paths: Vec<String> = positional.collect();
+    if paths.is_empty() {
+      paths.push(String::from(STDIN));
+    }
+    let case_sensitive = !ignore_case && (smart_case || env::var("CASE_INSENSITIVE").is_err());
+
+    Ok(Config { patterns, paths, case_sensitive, smart_case, regex, whole_word, invert, include, exclude, output, threads, replace, write, dry_run })
+  }
+
+  /// Builds the matcher selecting the lines to print.
+  pub fn matcher(&self) -> Result<Box<dyn Matcher>, regex::Error> {
+    let mut matchers = Vec::new();
+    for pattern in &self.patterns {
+      let case_sensitive = if self.smart_case {
+        matcher::has_uppercase(pattern, self.regex)
+      } else {
+        self.case_sensitive
+      };
+      let matcher: Box<dyn Matcher> = if self.regex {
+        Box::new(RegexMatcher::new(pattern, case_sensitive)?)
+      } else if case_sensitive {
+        Box::new(Contains::new(pattern))
+      } else {
+        Box::new(ContainsIgnoreCase::new(pattern))
+      };
+      matchers.push(if self.whole_word { Box::new(WholeWord::new(matcher)) } else { matcher });
     }
-    let query = args[1].clone();
-    let filename = args[2].clone();
-    let case_sensitive = env::var("CASE_INSENSITIVE").is_err();
 
-    Ok(Config { query, filename, case_sensitive })
+    let matcher = if matchers.len() == 1 {
+      matchers.pop().unwrap()
+    } else {
+      Box::new(AnyOf::new(matchers))
+    };
+    Ok(if self.invert { Box::new(Invert::new(matcher)) } else { matcher })
   }
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-  let contents = fs::read_to_string(config.filename)?;
+fn number(option: String, value: String) -> Result<usize, ConfigError> {
+  value.parse().map_err(|_| ConfigError::InvalidValue { option, value })
+}
+
+/// Searches every file of the paths. A file that can't be read is reported
+/// and skipped, the search goes on with the others.
+///
+/// Files are read one line at a time, so their size doesn't matter.
+///
+/// Returns whether any line was selected.
+pub fn run(config: Config) -> Result<bool, Box<dyn Error>> {
+  let options = PrintOptions { color: io::stdout().is_terminal(), ..config.output.clone() };
+  let summary = search_paths(&config, &options, io::stdout().lock())?;
 
-  let results = if config.case_sensitive {
-    search(&config.query, &contents)
-  } else {
-    search_case_insensitive(&config.query, &contents)
+  if summary.failed > 0 {
+    return Err(format!("{} file(s) couldn't be searched", summary.failed).into());
+  }
+  Ok(summary.selected > 0)
+}
+
+/// What searching the paths found.
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+  /// How many lines were selected
+  pub selected: usize,
+  /// How many files couldn't be searched
+  pub failed: usize,
+}
+
+/// What a file is read from.
+enum Input {
+  Stdin,
+  File(PathBuf),
+}
+
+/// Searches the files on `config.threads` threads and writes what each of
+/// them prints to `out`, one file after the other in the order they were
+/// found. With a single thread or a single input, the lines are written as
+/// soon as they are found instead.
+///
+/// With `--write` the files are edited instead, and with `--dry-run` the
+/// diffs of these edits are written.
+///
+/// The files that couldn't be searched are reported on stderr.
+pub fn search_paths<W: Write>(config: &Config, options: &PrintOptions, mut out: W) -> Result<Summary, Box<dyn Error>> {
+  // Lines only say which file they come from when there can be several
+  let show_path = config.paths.len() > 1 || config.paths.iter().any(|path| Path::new(path).is_dir());
+  let threads = if config.threads == 0 { pool::default_threads() } else { config.threads };
+  let search = Search {
+    matcher: config.matcher()?,
+    replacement: config.replace.as_deref(),
+    rewrites: config.write || config.dry_run,
+    dry_run: config.dry_run,
+    show_path,
   };
 
-  for line in results {
-    println!("{}", line);
+  let produce = |send: &mut dyn FnMut(Result<Input, String>)| {
+    for path in &config.paths {
+      if path == STDIN {
+        send(Ok(Input::Stdin));
+        continue;
+      }
+      for entry in Walk::new(&[path], Filter::new(&config.include, &config.exclude)) {
+        send(entry.map(Input::File).map_err(|e| e.to_string()));
+      }
+    }
+  };
+
+  let mut summary = Summary::default();
+  let mut report = |searched: Result<usize, String>| match searched {
+    Ok(selected) => summary.selected += selected,
+    Err(e) => {
+      eprintln!("{}", e);
+      summary.failed += 1;
+    }
+  };
+
+  if threads == 1 || !show_path {
+    // Nothing to wait for, the printer writes straight to `out`
+    let mut printer = Printer::new(&mut out, options.clone());
+    let mut output = Ok(());
+    produce(&mut |input| {
+      if output.is_err() {
+        return;
+      }
+      match input {
+        Ok(input) => match search.input(input, &mut printer) {
+          Ok(searched) => report(searched),
+          Err(e) => output = Err(e),
+        },
+        Err(e) => report(Err(e)),
+      }
+    });
+    output?;
+  } else {
+    let work = |input: Result<Input, String>| {
+      let mut printer = Printer::new(Vec::new(), options.clone());
+      // Writing to memory doesn't fail
+      let searched = input.and_then(|input| search.input(input, &mut printer).unwrap());
+      searched.map(|selected| (selected, printer.into_inner()))
+    };
+    let mut printed_any = false;
+    pool::run_ordered(threads, produce, work, |searched| {
+      let output = match searched {
+        Ok((selected, output)) => {
+          report(Ok(selected));
+          output
+        }
+        Err(e) => {
+          report(Err(e));
+          return Ok(());
+        }
+      };
+      if output.is_empty() {
+        return Ok(());
+      }
+      // Each file's printer only separates its own groups
+      if let (true, false, Some(separator)) = (printed_any, search.rewrites, options.group_separator()) {
+        writeln!(out, "{}", separator)?;
+      }
+      printed_any = true;
+      out.write_all(&output)
+    })?;
+  }
+  out.flush()?;
+  Ok(summary)
+}
+
+// What searching an input needs, shared by the workers
+struct Search<'a> {
+  matcher: Box<dyn Matcher>,
+  replacement: Option<&'a str>,
+  rewrites: bool,
+  dry_run: bool,
+  show_path: bool,
+}
+
+impl Search<'_> {
+  // Prints what the input has to show and returns how many lines were
+  // selected, or changed by a rewrite. The outer error comes from writing the
+  // output, which stops the search, the inner one from reading the input,
+  // which only skips it.
+  fn input<W: Write>(&self, input: Input, printer: &mut Printer<W>) -> io::Result<Result<usize, String>> {
+    let path = match input {
+      Input::Stdin if self.rewrites => return Ok(Err(String::from("standard input can't be rewritten"))),
+      Input::File(path) if self.rewrites => {
+        let replacement = self.replacement.unwrap_or_default();
+        return match rewrite_file(&path, &*self.matcher, replacement, self.dry_run) {
+          Ok((changes, diff)) => printer.get_mut().write_all(&diff).map(|()| Ok(changes)),
+          Err(e) => Ok(Err(format!("{}: {}", path.display(), e))),
+        };
+      }
+      Input::Stdin => None,
+      Input::File(path) => Some(path),
+    };
+
+    printer.begin_file(path.as_deref().unwrap_or(Path::new("(standard input)")), self.show_path);
+    let before = printer.selected();
+    // Both kinds of errors come out of the line reader, the output ones are
+    // kept aside to tell them apart
+    let mut output_error = None;
+    let print = |number: usize, offset: usize, line: &str| {
+      print_line(printer, &*self.matcher, self.replacement, number, offset, line).map_err(|e| {
+        let error = io::Error::new(e.kind(), e.to_string());
+        output_error = Some(e);
+        error
+      })
+    };
+    let read = match &path {
+      Some(path) => lines::file_lines(path, print).map_err(|e| format!("{}: {}", path.display(), e)),
+      None => lines::read_lines(io::stdin().lock(), print).map_err(|e| e.to_string()),
+    };
+    if let Some(e) = output_error {
+      return Err(e);
+    }
+    if let Err(e) = read {
+      return Ok(Err(e));
+    }
+    printer.end_file()?;
+    Ok(Ok(printer.selected() - before))
   }
+}
 
-  Ok(())
+fn print_line<W: io::Write, M: Matcher + ?Sized>(
+  printer: &mut Printer<W>,
+  matcher: &M,
+  replacement: Option<&str>,
+  number: usize,
+  offset: usize,
+  line: &str,
+) -> io::Result<()> {
+  match (LineMatch::find(matcher, number, offset, line), replacement) {
+    (Some(m), Some(replacement)) => {
+      let replaced = replace::replace_line(matcher, &m, replacement);
+      printer.matched(&LineMatch { number, offset, line: &replaced.text, spans: replaced.spans })
+    }
+    (Some(m), None) => printer.matched(&m),
+    (None, _) => printer.context(number, offset, line),
+  }
 }
 
-pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-  let mut results = Vec::new();
-  for line in contents.lines() {
-    if line.contains(query) {
-      results.push(line);
+/// Replaces the matches in the file, or only returns the diff of doing it
+/// on a dry run. Returns how many lines change.
+fn rewrite_file<M: Matcher + ?Sized>(path: &Path, matcher: &M, replacement: &str, dry_run: bool) -> io::Result<(usize, Vec<u8>)> {
+  let bytes = std::fs::read(path)?;
+  if lines::is_binary(&bytes) {
+    return Ok((0, Vec::new()));
+  }
+  // Writing back a lossy version would damage the file
+  let contents = String::from_utf8(bytes)
+    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "can't rewrite a file that isn't valid UTF-8"))?;
+
+  let edits = replace::rewrite(matcher, &contents, replacement);
+  let changes = replace::changes(&edits);
+  if dry_run {
+    return Ok((changes, replace::unified_diff(&path.display().to_string(), &edits).into_bytes()));
+  }
+  if changes > 0 {
+    replace::write_atomically(path, &replace::edited(&edits))?;
+  }
+  Ok((changes, Vec::new()))
+}
+
+/// A selected line and where the matches are in it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineMatch<'a> {
+  /// Starting at 1
+  pub number: usize,
+  /// Where the line starts in the contents, in bytes
+  pub offset: usize,
+  pub line: &'a str,
+  /// The byte ranges of the matches in the line, none for inverted searches
+  pub spans: Vec<Range<usize>>,
+}
+
+impl<'a> LineMatch<'a> {
+  /// Returns the matches if the matcher selects the line.
+  pub fn find<M: Matcher + ?Sized>(matcher: &M, number: usize, offset: usize, line: &'a str) -> Option<LineMatch<'a>> {
+    if !matcher.is_match(line) {
+      return None;
+    }
+
+    let mut spans = Vec::new();
+    let mut start = 0;
+    while let Some(span) = matcher.find_at(line, start) {
+      spans.push(span.clone());
+      // Empty matches still have to move forward
+      start = if span.is_empty() {
+        match line[span.end..].chars().next() {
+          Some(c) => span.end + c.len_utf8(),
+          None => break,
+        }
+      } else {
+        span.end
+      };
     }
+    Some(LineMatch { number, offset, line, spans })
   }
-  results
+}
+
+/// The lines of the contents with their number and byte offset.
+pub fn numbered_lines(contents: &str) -> impl Iterator<Item = (usize, usize, &str)> {
+  let mut offset = 0;
+  contents.split_inclusive('\n').enumerate().map(move |(i, line)| {
+    let start = offset;
+    offset += line.len();
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    (i + 1, start, line.strip_suffix('\r').unwrap_or(line))
+  })
+}
+
+pub fn search<'a>(query: &str, contents: &'a str) -> Vec<LineMatch<'a>> {
+  search_with(&Contains::new(query), contents)
 }
 
 pub fn search_case_insensitive<'a>(
   query: &str,
   contents: &'a str,
-) -> Vec<&'a str> {
-  let query = query.to_lowercase();
-  let mut results = Vec::new();
+) -> Vec<LineMatch<'a>> {
+  search_with(&ContainsIgnoreCase::new(query), contents)
+}
 
-  for line in contents.lines() {
-    if line.to_lowercase().contains(&query) {
-      results.push(line);
+/// Returns the lines selected by the matcher.
+pub fn search_with<'a, M: Matcher + ?Sized>(matcher: &M, contents: &'a str) -> Vec<LineMatch<'a>> {
+  let mut results = Vec::new();
+  for (number, offset, line) in numbered_lines(contents) {
+    if let Some(m) = LineMatch::find(matcher, number, offset, line) {
+      results.push(m);
     }
   }
-
   results
 }
 
@@ -75,19 +467,20 @@ mod tests {
       Ok(_) => {
         panic!("It should have returned an error");
       },
-      Err(message) => {
-        assert_eq!(message, "not enough arguments");
+      Err(error) => {
+        assert_eq!(error, ConfigError::MissingPattern);
       },
     }
 
     let r = Config::new(&[String::from("param1"), String::from("param2")]);
 
     match r {
-      Ok(_) => {
-        panic!("It should have returned an error");
+      Ok(config) => {
+        assert_eq!(config.patterns, vec!["param2"]);
+        assert_eq!(config.paths, vec![STDIN]);
       },
-      Err(message) => {
-        assert_eq!(message, "not enough arguments");
+      Err(_) => {
+        panic!("It should not have returned an error");
       },
     }
 
@@ -95,8 +488,8 @@ mod tests {
 
     match r {
       Ok(config) => {
-        assert_eq!(config.query, "param2");
-        assert_eq!(config.filename, "param3");
+        assert_eq!(config.patterns, vec!["param2"]);
+        assert_eq!(config.paths, vec!["param3"]);
       },
       Err(_) => {
         panic!("It should not have returned an error");
@@ -113,7 +506,10 @@ safe, fast, productive.
 Pick three.
 Duct tape.";
 
-    assert_eq!(vec!["safe, fast, productive."], search(query, contents));
+    let results = search(query, contents);
+    assert_eq!(vec!["safe, fast, productive."], lines(&results));
+    assert_eq!((2, 6), (results[0].number, results[0].offset));
+    assert_eq!(15..19, results[0].spans[0]);
   }
 
   #[test]
@@ -125,6 +521,175 @@ safe, fast, productive.
 Pick three.
 Trust me.";
 
-    assert_eq!(vec!["Rust:", "Trust me."], search_case_insensitive(query, contents));
+    assert_eq!(vec!["Rust:", "Trust me."], lines(&search_case_insensitive(query, contents)));
+  }
+
+  fn lines<'a>(results: &[LineMatch<'a>]) -> Vec<&'a str> {
+    results.iter().map(|m| m.line).collect()
+  }
+
+  fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+  }
+
+  #[test]
+  fn config_search_modes() {
+    let config = Config::new(&args(&["minigrep", "-e", "you", "--regex", "-e", "fr?og", "-w", "-v", "poem.txt"])).unwrap();
+    assert_eq!(config.patterns, vec!["you", "fr?og"]);
+    assert_eq!(config.paths, vec!["poem.txt"]);
+    assert!(config.regex && config.whole_word && config.invert);
+
+    let r = Config::new(&args(&["minigrep", "poem.txt", "-e"]));
+    assert_eq!(r.err(), Some(ConfigError::MissingValue(String::from("-e"))));
+  }
+
+  #[test]
+  fn config_paths_and_globs() {
+    let config = Config::new(&args(&["minigrep", "frog", "src", "poem.txt", "--include", "*.txt", "--exclude", "target"])).unwrap();
+    assert_eq!(config.patterns, vec!["frog"]);
+    assert_eq!(config.paths, vec!["src", "poem.txt"]);
+    assert_eq!(config.include, vec!["*.txt"]);
+    assert_eq!(config.exclude, vec!["target"]);
+
+    let r = Config::new(&args(&["minigrep", "frog", "src", "--include"]));
+    assert_eq!(r.err(), Some(ConfigError::MissingValue(String::from("--include"))));
+  }
+
+  #[test]
+  fn config_output() {
+    let config = Config::new(&args(&["minigrep", "-n", "-b", "-C", "2", "-A", "1", "frog", "poem.txt"])).unwrap();
+    assert_eq!(config.output, PrintOptions { line_number: true, byte_offset: true, before: 2, after: 1, ..PrintOptions::default() });
+
+    let r = Config::new(&args(&["minigrep", "-A", "many", "frog", "poem.txt"]));
+    assert_eq!(r.err(), Some(ConfigError::InvalidValue { option: String::from("-A"), value: String::from("many") }));
+  }
+
+  #[test]
+  fn config_flags() {
+    let config = Config::new(&args(&["minigrep", "-ivn", "--", "-frog-", "poem.txt"])).unwrap();
+    assert_eq!(config.patterns, vec!["-frog-"]);
+    assert_eq!(config.paths, vec!["poem.txt"]);
+    assert!(!config.case_sensitive && config.invert && config.output.line_number);
+
+    let config = Config::new(&args(&["minigrep", "--count", "--json", "frog"])).unwrap();
+    assert_eq!(Format::Json, config.output.format);
+
+    assert_eq!(Config::new(&args(&["minigrep", "frog", "--help"])).err(), Some(ConfigError::HelpRequested));
+    assert_eq!(Config::new(&args(&["minigrep", "-V"])).err(), Some(ConfigError::VersionRequested));
+  }
+
+  #[test]
+  fn searches_files_in_order() {
+    let root = env::temp_dir().join(format!("minigrep_parallel_{}", std::process::id()));
+    for i in 0..20 {
+      let dir = root.join(format!("dir{:02}", i));
+      std::fs::create_dir_all(&dir).unwrap();
+      std::fs::write(dir.join("poem.txt"), format!("frog {}\nbog\nfrog again\n", i)).unwrap();
+    }
+
+    let mut config = Config::new(&args(&["minigrep", "-A1", "-j4", "frog", root.to_str().unwrap()])).unwrap();
+    assert_eq!(4, config.threads);
+    let mut out = Vec::new();
+    let summary = search_paths(&config, &config.output, &mut out).unwrap();
+    assert_eq!(Summary { selected: 40, failed: 0 }, summary);
+
+    let expected: Vec<String> = (0..20)
+      .map(|i| {
+        let path = root.join(format!("dir{:02}", i)).join("poem.txt");
+        let path = path.display();
+        format!("{path}:frog {i}\n{path}-bog\n{path}:frog again\n", path = path, i = i)
+      })
+      .collect();
+    assert_eq!(expected.join("--\n"), String::from_utf8(out).unwrap());
+
+    config.paths.push(String::from("missing"));
+    assert_eq!(1, search_paths(&config, &config.output, io::sink()).unwrap().failed);
+
+    config.output.format = Format::FilesWithMatches;
+    config.paths.pop();
+    let mut out = Vec::new();
+    search_paths(&config, &config.output, &mut out).unwrap();
+    assert_eq!(20, String::from_utf8(out).unwrap().lines().count());
+    std::fs::remove_dir_all(root).unwrap();
+  }
+
+  #[test]
+  fn output_errors_stop_the_search() {
+    struct Closed;
+
+    impl Write for Closed {
+      fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+      }
+
+      fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+      }
+    }
+
+    // Written as they are found, so the error isn't taken for one reading
+    // the file
+    let config = Config::new(&args(&["minigrep", "frog", "poem.txt"])).unwrap();
+    let error = search_paths(&config, &config.output, Closed).unwrap_err();
+    assert_eq!("closed", error.to_string());
+  }
+
+  #[test]
+  fn smart_case() {
+    let contents = "\
+How public, like a frog
+To an admiring Bog!";
+    let config = Config::new(&args(&["minigrep", "-S", "-e", "FROG", "-e", "bog"])).unwrap();
+    assert_eq!(vec!["To an admiring Bog!"], lines(&search_with(&config.matcher().unwrap(), contents)));
+
+    let config = Config::new(&args(&["minigrep", "-S", "-i", "FROG"])).unwrap();
+    assert!(!config.smart_case && !config.case_sensitive);
+  }
+
+  #[test]
+  fn replacements() {
+    let path = env::temp_dir().join(format!("minigrep_rewrite_{}.txt", std::process::id()));
+    std::fs::write(&path, "How public, like a frog\nTo an admiring bog!\n").unwrap();
+    let path_arg = path.to_str().unwrap();
+
+    let config = Config::new(&args(&["minigrep", "--regex", "-r", "[$1]", r"(\w+)og\b", path_arg])).unwrap();
+    let mut out = Vec::new();
+    assert_eq!(2, search_paths(&config, &config.output, &mut out).unwrap().selected);
+    assert_eq!("How public, like a [fr]\nTo an admiring [b]!\n", String::from_utf8(out).unwrap());
+
+    let config = Config::new(&args(&["minigrep", "--dry-run", "-r", "toad", "frog", path_arg])).unwrap();
+    let mut out = Vec::new();
+    search_paths(&config, &config.output, &mut out).unwrap();
+    assert!(String::from_utf8(out).unwrap().contains("-How public, like a frog\n+How public, like a toad\n"));
+
+    let config = Config::new(&args(&["minigrep", "--write", "-r", "toad", "frog", path_arg])).unwrap();
+    assert_eq!(1, search_paths(&config, &config.output, io::sink()).unwrap().selected);
+    assert_eq!("How public, like a toad\nTo an admiring bog!\n", std::fs::read_to_string(&path).unwrap());
+    std::fs::remove_file(path).unwrap();
+
+    let r = Config::new(&args(&["minigrep", "--write", "frog"]));
+    assert_eq!(r.err(), Some(ConfigError::MissingReplace(String::from("--write"))));
+  }
+
+  #[test]
+  fn match_positions() {
+    let contents = "frogs\r\nno frog, one frog\n";
+    assert_eq!(vec![(1, 0, "frogs"), (2, 7, "no frog, one frog")], numbered_lines(contents).collect::<Vec<_>>());
+
+    let m = LineMatch::find(&Contains::new("frog"), 2, 7, "no frog, one frog").unwrap();
+    assert_eq!(vec![3..7, 13..17], m.spans);
+    let m = LineMatch::find(&RegexMatcher::new("x*", true).unwrap(), 1, 0, "ab").unwrap();
+    assert_eq!(vec![0..0, 1..1, 2..2], m.spans);
+  }
+
+  #[test]
+  fn several_patterns() {
+    let config = Config::new(&args(&["minigrep", "-e", "frog", "-e", "bog", "-w", "poem.txt"])).unwrap();
+    let contents = "\
+How public, like a frog
+To an admiring bog!
+Boggy frogs";
+
+    assert_eq!(vec!["How public, like a frog", "To an admiring bog!"], lines(&search_with(&config.matcher().unwrap(), contents)));
   }
 }
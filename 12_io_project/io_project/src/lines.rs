@@ -0,0 +1,153 @@
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+// How much of a file is looked at to decide whether it is binary
+const BINARY_SNIFF_SIZE: usize = 8192;
+/// Files at least this big are memory-mapped instead of read through a buffer
+pub const MMAP_THRESHOLD: u64 = 64 * 1024 * 1024;
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Whether the start of a file looks binary, because it has a NUL byte.
+pub fn is_binary(start: &[u8]) -> bool {
+  start[..start.len().min(BINARY_SNIFF_SIZE)].contains(&0)
+}
+
+/// Calls `f` with the number, byte offset and text of every line, reading
+/// one line at a time so memory only grows with the longest line.
+///
+/// Invalid UTF-8 is replaced with `�`. Returns `false`, without calling `f`,
+/// if the input looks binary.
+pub fn read_lines<R, F>(mut reader: R, mut f: F) -> io::Result<bool>
+where
+  R: BufRead,
+  F: FnMut(usize, usize, &str) -> io::Result<()>,
+{
+  if is_binary(reader.fill_buf()?) {
+    return Ok(false);
+  }
+
+  let mut buffer = Vec::new();
+  let (mut number, mut offset) = (1, 0);
+  loop {
+    buffer.clear();
+    let read = reader.read_until(b'\n', &mut buffer)?;
+    if read == 0 {
+      return Ok(true);
+    }
+    f(number, offset, &String::from_utf8_lossy(strip_newline(&buffer)))?;
+    number += 1;
+    offset += read;
+  }
+}
+
+/// Like `read_lines`, for contents that are already in memory.
+pub fn slice_lines<F>(bytes: &[u8], mut f: F) -> io::Result<bool>
+where
+  F: FnMut(usize, usize, &str) -> io::Result<()>,
+{
+  if is_binary(bytes) {
+    return Ok(false);
+  }
+
+  let mut offset = 0;
+  for (i, line) in bytes.split_inclusive(|&b| b == b'\n').enumerate() {
+    f(i + 1, offset, &String::from_utf8_lossy(strip_newline(line)))?;
+    offset += line.len();
+  }
+  Ok(true)
+}
+
+/// Goes through the lines of a file, memory-mapping it if it is bigger than
+/// `MMAP_THRESHOLD`.
+pub fn file_lines<P, F>(path: P, f: F) -> io::Result<bool>
+where
+  P: AsRef<Path>,
+  F: FnMut(usize, usize, &str) -> io::Result<()>,
+{
+  file_lines_with_threshold(path.as_ref(), MMAP_THRESHOLD, f)
+}
+
+fn file_lines_with_threshold<F>(path: &Path, threshold: u64, f: F) -> io::Result<bool>
+where
+  F: FnMut(usize, usize, &str) -> io::Result<()>,
+{
+  let file = File::open(path)?;
+  if file.metadata()?.len() < threshold {
+    return read_lines(BufReader::with_capacity(BUFFER_SIZE, file), f);
+  }
+
+  // Safety: the map is only read, and only while the file is open. Another
+  // process truncating the file meanwhile would make reads fault, grep tools
+  // accept that in exchange for not copying the file.
+  let map = unsafe { Mmap::map(&file)? };
+  slice_lines(&map, f)
+}
+
+fn strip_newline(line: &[u8]) -> &[u8] {
+  let line = line.strip_suffix(b"\n").unwrap_or(line);
+  line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs;
+  use std::io::Cursor;
+
+  const CONTENTS: &[u8] = b"frogs\r\nno \xff frog\n\nbog";
+
+  fn collect<F: FnOnce(&mut dyn FnMut(usize, usize, &str) -> io::Result<()>) -> io::Result<bool>>(
+    read: F,
+  ) -> Option<Vec<(usize, usize, String)>> {
+    let mut lines = Vec::new();
+    let text = read(&mut |number, offset, line| {
+      lines.push((number, offset, line.to_string()));
+      Ok(())
+    })
+    .unwrap();
+    if text {
+      Some(lines)
+    } else {
+      None
+    }
+  }
+
+  fn expected() -> Option<Vec<(usize, usize, String)>> {
+    Some(vec![
+      (1, 0, String::from("frogs")),
+      (2, 7, String::from("no \u{fffd} frog")),
+      (3, 17, String::new()),
+      (4, 18, String::from("bog")),
+    ])
+  }
+
+  #[test]
+  fn reads_lines_lossily() {
+    assert_eq!(expected(), collect(|f| read_lines(Cursor::new(CONTENTS), f)));
+    assert_eq!(expected(), collect(|f| slice_lines(CONTENTS, f)));
+    // A tiny buffer still reads whole lines
+    assert_eq!(expected(), collect(|f| read_lines(BufReader::with_capacity(2, CONTENTS), f)));
+  }
+
+  #[test]
+  fn skips_binary_input() {
+    assert_eq!(None, collect(|f| read_lines(Cursor::new(b"frog\0bog"), f)));
+    assert_eq!(None, collect(|f| slice_lines(b"frog\0bog", f)));
+  }
+
+  #[test]
+  fn maps_big_files() {
+    let path = std::env::temp_dir().join(format!("minigrep_lines_{}", std::process::id()));
+    fs::write(&path, CONTENTS).unwrap();
+
+    assert_eq!(expected(), collect(|f| file_lines_with_threshold(&path, 0, f)));
+    assert_eq!(expected(), collect(|f| file_lines(&path, f)));
+
+    fs::remove_file(path).unwrap();
+  }
+}
@@ -14,13 +14,23 @@ fn main() {
 
   // unwrap_or_else returns the Ok or executes the block with the Err
   let config = Config::new(&args).unwrap_or_else(|err| {
+    // --help and --version aren't mistakes
+    if err.is_request() {
+      println!("{}", err);
+      process::exit(0);
+    }
     eprintln!("Problem parsing arguments: {}", err);
-    process::exit(1);
+    eprintln!("Try 'minigrep --help' for more information.");
+    process::exit(2);
   });
 
-  // We use this instead of unwrap_or_else because we don't care about the Ok
-  if let Err(e) = io_project::run(config) {
-    eprintln!("Application error: {}", e);
-    process::exit(1);
+  // Like grep, 0 when a line was selected, 1 when none was and 2 on errors
+  match io_project::run(config) {
+    Ok(true) => {}
+    Ok(false) => process::exit(1),
+    Err(e) => {
+      eprintln!("Application error: {}", e);
+      process::exit(2);
+    }
   }
 }
@@ -0,0 +1,327 @@
+use std::ops::Range;
+
+use regex::Regex;
+use regex::RegexBuilder;
+
+/// Finds the matches of a pattern in a line.
+///
+/// Matchers are shared by the threads searching files.
+pub trait Matcher: Send + Sync {
+  /// Returns the first match starting at or after `start`, as a byte range of
+  /// the line.
+  fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>>;
+
+  fn is_match(&self, line: &str) -> bool {
+    self.find_at(line, 0).is_some()
+  }
+
+  /// Appends what replaces the match to `out`. The replacement is taken as
+  /// it is, except by matchers with capture groups to refer to.
+  fn expand(&self, _line: &str, _m: Range<usize>, replacement: &str, out: &mut String) {
+    out.push_str(replacement);
+  }
+}
+
+impl<M: Matcher + ?Sized> Matcher for Box<M> {
+  fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+    (**self).find_at(line, start)
+  }
+
+  fn is_match(&self, line: &str) -> bool {
+    (**self).is_match(line)
+  }
+
+  fn expand(&self, line: &str, m: Range<usize>, replacement: &str, out: &mut String) {
+    (**self).expand(line, m, replacement, out)
+  }
+}
+
+/// The query as a plain substring.
+pub struct Contains {
+  query: String,
+}
+
+impl Contains {
+  pub fn new(query: &str) -> Contains {
+    Contains { query: query.to_string() }
+  }
+}
+
+impl Matcher for Contains {
+  fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+    let i = start + line[start..].find(&self.query)?;
+    Some(i..i + self.query.len())
+  }
+}
+
+/// The query as a substring, ignoring case.
+///
+/// Both sides are case folded one character at a time, so nothing is
+/// allocated per line and the matches are ranges of the original line even
+/// when folding changes lengths, as `İ` becoming `i̇` or `ß` becoming `ss`
+/// do. A match always covers whole characters of the line: `ss` matches `ß`
+/// but `s` alone doesn't.
+pub struct ContainsIgnoreCase {
+  folded: Vec<char>,
+}
+
+impl ContainsIgnoreCase {
+  pub fn new(query: &str) -> ContainsIgnoreCase {
+    let folded = query.chars().flat_map(|c| {
+      let (chars, len) = fold(c);
+      IntoIterator::into_iter(chars).take(len)
+    });
+    ContainsIgnoreCase { folded: folded.collect() }
+  }
+
+  // Where a match starting at `at` ends, if there is one
+  fn end_of_match(&self, line: &str, at: usize) -> Option<usize> {
+    let mut query = self.folded.iter();
+    for (i, c) in line[at..].char_indices() {
+      let (chars, len) = fold(c);
+      for folded in &chars[..len] {
+        if query.next() != Some(folded) {
+          return None;
+        }
+      }
+      if query.len() == 0 {
+        return Some(at + i + c.len_utf8());
+      }
+    }
+    None
+  }
+}
+
+impl Matcher for ContainsIgnoreCase {
+  fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+    if self.folded.is_empty() {
+      return Some(start..start);
+    }
+    line[start..]
+      .char_indices()
+      .find_map(|(i, _)| self.end_of_match(line, start + i).map(|end| start + i..end))
+  }
+}
+
+/// The case folding of a character, at most 3 characters long.
+fn fold(c: char) -> ([char; 3], usize) {
+  match c {
+    // Lowercasing leaves these as they are, folding doesn't
+    'ß' | 'ẞ' => (['s', 's', '\0'], 2),
+    'ς' => (['σ', '\0', '\0'], 1),
+    _ => {
+      let mut chars = ['\0'; 3];
+      let mut len = 0;
+      for lower in c.to_lowercase() {
+        chars[len] = lower;
+        len += 1;
+      }
+      (chars, len)
+    }
+  }
+}
+
+/// Whether the pattern has an uppercase letter, for smart case. Escapes such
+/// as `\S` in regular expressions don't count.
+pub fn has_uppercase(pattern: &str, regex: bool) -> bool {
+  let mut chars = pattern.chars();
+  while let Some(c) = chars.next() {
+    if regex && c == '\\' {
+      chars.next();
+    } else if c.is_uppercase() {
+      return true;
+    }
+  }
+  false
+}
+
+/// The query as a regular expression.
+pub struct RegexMatcher {
+  regex: Regex,
+}
+
+impl RegexMatcher {
+  pub fn new(pattern: &str, case_sensitive: bool) -> Result<RegexMatcher, regex::Error> {
+    let regex = RegexBuilder::new(pattern).case_insensitive(!case_sensitive).build()?;
+    Ok(RegexMatcher { regex })
+  }
+}
+
+impl Matcher for RegexMatcher {
+  fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+    self.regex.find_at(line, start).map(|m| m.range())
+  }
+
+  /// Expands `$1` or `${name}` to what the groups captured.
+  fn expand(&self, line: &str, m: Range<usize>, replacement: &str, out: &mut String) {
+    match self.regex.captures_at(line, m.start) {
+      Some(captures) => captures.expand(replacement, out),
+      None => out.push_str(replacement),
+    }
+  }
+}
+
+/// Matches when any of the matchers does, the leftmost match wins.
+pub struct AnyOf {
+  matchers: Vec<Box<dyn Matcher>>,
+}
+
+impl AnyOf {
+  pub fn new(matchers: Vec<Box<dyn Matcher>>) -> AnyOf {
+    AnyOf { matchers }
+  }
+}
+
+impl Matcher for AnyOf {
+  fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+    self
+      .matchers
+      .iter()
+      .filter_map(|matcher| matcher.find_at(line, start))
+      // The longest one when several start at the same place
+      .min_by_key(|m| (m.start, std::cmp::Reverse(m.end)))
+  }
+
+  fn is_match(&self, line: &str) -> bool {
+    self.matchers.iter().any(|matcher| matcher.is_match(line))
+  }
+
+  // The matcher that found the match expands it
+  fn expand(&self, line: &str, m: Range<usize>, replacement: &str, out: &mut String) {
+    match self.matchers.iter().find(|matcher| matcher.find_at(line, m.start) == Some(m.clone())) {
+      Some(matcher) => matcher.expand(line, m, replacement, out),
+      None => out.push_str(replacement),
+    }
+  }
+}
+
+/// Only keeps the matches that are whole words.
+pub struct WholeWord<M> {
+  inner: M,
+}
+
+impl<M: Matcher> WholeWord<M> {
+  pub fn new(inner: M) -> WholeWord<M> {
+    WholeWord { inner }
+  }
+}
+
+impl<M: Matcher> Matcher for WholeWord<M> {
+  fn find_at(&self, line: &str, mut start: usize) -> Option<Range<usize>> {
+    loop {
+      let m = self.inner.find_at(line, start)?;
+      let before = line[..m.start].chars().next_back();
+      let after = line[m.end..].chars().next();
+      if !m.is_empty() && !before.is_some_and(is_word) && !after.is_some_and(is_word) {
+        return Some(m);
+      }
+      // Tries again from the next character
+      start = m.start + line[m.start..].chars().next()?.len_utf8();
+    }
+  }
+
+  fn expand(&self, line: &str, m: Range<usize>, replacement: &str, out: &mut String) {
+    self.inner.expand(line, m, replacement, out)
+  }
+}
+
+fn is_word(c: char) -> bool {
+  c.is_alphanumeric() || c == '_'
+}
+
+/// Selects the lines the inner matcher doesn't match.
+///
+/// Those lines have nothing to point at, so `find_at` never returns a match.
+pub struct Invert<M> {
+  inner: M,
+}
+
+impl<M: Matcher> Invert<M> {
+  pub fn new(inner: M) -> Invert<M> {
+    Invert { inner }
+  }
+}
+
+impl<M: Matcher> Matcher for Invert<M> {
+  fn find_at(&self, _line: &str, _start: usize) -> Option<Range<usize>> {
+    None
+  }
+
+  fn is_match(&self, line: &str) -> bool {
+    !self.inner.is_match(line)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn substrings() {
+    assert_eq!(Some(3..7), Contains::new("duct").find_at("productive duct", 0));
+    assert_eq!(Some(11..15), Contains::new("duct").find_at("productive duct", 5));
+    assert_eq!(Some(0..4), ContainsIgnoreCase::new("rUsT").find_at("Rust", 0));
+    assert!(!ContainsIgnoreCase::new("rUsT").is_match("Rus t"));
+  }
+
+  #[test]
+  fn case_folding_keeps_original_offsets() {
+    // İ is 2 bytes but lowercases to 3
+    assert_eq!(Some(2..9), ContainsIgnoreCase::new("stanbul").find_at("İSTANBUL", 0));
+    assert_eq!(Some(10..12), ContainsIgnoreCase::new("OR").find_at("İstanbul or", 0));
+    assert_eq!(Some(0..2), ContainsIgnoreCase::new("i̇").find_at("İ", 0));
+    assert_eq!(Some(2..6), ContainsIgnoreCase::new("BODY").find_at("İbodyé", 0));
+    assert_eq!(Some(3..7), WholeWord::new(ContainsIgnoreCase::new("body")).find_at("İ body é", 0));
+    assert_eq!(None, WholeWord::new(ContainsIgnoreCase::new("body")).find_at("İbodyé", 0));
+
+    assert_eq!(Some(4..11), ContainsIgnoreCase::new("straße").find_at("Die STRASSE", 0));
+    assert_eq!(Some(0..7), ContainsIgnoreCase::new("STRASSE").find_at("Straße", 0));
+    assert_eq!(None, ContainsIgnoreCase::new("s").find_at("ß", 0));
+    assert_eq!(Some(0..8), ContainsIgnoreCase::new("οδος").find_at("ΟΔΟΣ", 0));
+    assert_eq!(Some(3..3), ContainsIgnoreCase::new("").find_at("abc", 3));
+  }
+
+  #[test]
+  fn uppercase_patterns() {
+    assert!(has_uppercase("Frog", false));
+    assert!(has_uppercase("\\S", false));
+    assert!(!has_uppercase("\\S+frog", true));
+    assert!(has_uppercase("\\sÉ", true));
+    assert!(!has_uppercase("straße", true));
+  }
+
+  #[test]
+  fn regex() {
+    let matcher = RegexMatcher::new(r"fr?og", true).unwrap();
+    assert_eq!(Some(14..18), matcher.find_at("How public, a frog", 0));
+    assert!(!matcher.is_match("A FROG"));
+    assert!(RegexMatcher::new(r"fr?og", false).unwrap().is_match("A FROG"));
+    assert!(RegexMatcher::new(r"(", true).is_err());
+  }
+
+  #[test]
+  fn any_of() {
+    let matcher = AnyOf::new(vec![Box::new(Contains::new("you")), Box::new(Contains::new("Who"))]);
+    assert_eq!(Some(14..17), matcher.find_at("I’m nobody! Who are you?", 0));
+    assert!(matcher.is_match("Are you nobody, too?"));
+    assert!(!matcher.is_match("How dreary to be somebody!"));
+  }
+
+  #[test]
+  fn whole_words() {
+    let matcher = WholeWord::new(Contains::new("body"));
+    assert!(!matcher.is_match("How dreary to be somebody!"));
+    assert_eq!(Some(20..24), matcher.find_at("somebody, nobody or body", 0));
+
+    let matcher = WholeWord::new(RegexMatcher::new("no.ody", true).unwrap());
+    assert_eq!(Some(4..10), matcher.find_at("Are nobody, too?", 0));
+  }
+
+  #[test]
+  fn inverted() {
+    let matcher = Invert::new(Contains::new("nobody"));
+    assert!(matcher.is_match("How dreary to be somebody!"));
+    assert!(!matcher.is_match("Are you nobody, too?"));
+    assert_eq!(None, matcher.find_at("How dreary to be somebody!", 0));
+  }
+}
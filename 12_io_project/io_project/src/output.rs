@@ -0,0 +1,372 @@
+use std::collections::VecDeque;
+use std::io;
+use std::io::Write;
+use std::ops::Range;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::LineMatch;
+
+const RESET: &str = "\x1b[0m";
+const PATH_COLOR: &str = "\x1b[35m";
+const NUMBER_COLOR: &str = "\x1b[32m";
+const SEPARATOR_COLOR: &str = "\x1b[36m";
+const MATCH_COLOR: &str = "\x1b[1;31m";
+
+/// What is printed for the selected lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Format {
+  /// The lines themselves, with their context
+  #[default]
+  Lines,
+  /// One JSON object per selected or context line
+  Json,
+  /// How many lines were selected in each file
+  Count,
+  /// The path of the files with a selected line
+  FilesWithMatches,
+}
+
+/// How the selected lines are printed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PrintOptions {
+  pub format: Format,
+  pub line_number: bool,
+  pub byte_offset: bool,
+  /// Lines of context printed before each selected line
+  pub before: usize,
+  /// Lines of context printed after each selected line
+  pub after: usize,
+  /// Highlights the matches with terminal colours
+  pub color: bool,
+}
+
+impl PrintOptions {
+  /// What goes between groups of lines, when there is context.
+  pub fn group_separator(&self) -> Option<String> {
+    if self.format == Format::Lines && (self.before > 0 || self.after > 0) {
+      Some(paint(self.color, SEPARATOR_COLOR, "--"))
+    } else {
+      None
+    }
+  }
+}
+
+/// Prints the lines of the files one at a time, keeping just enough of them
+/// to show the context of the next selected line.
+///
+/// Selected lines are printed as `path:number:offset:line` and context lines
+/// as `path-number-offset-line`, with a `--` between groups that aren't next
+/// to each other.
+///
+/// As JSON, each line is an object such as
+///
+/// ```text
+/// {"type":"match","path":"poem.txt","line_number":7,"byte_offset":142,
+///  "text":"How public, like a frog","submatches":[{"column":20,"text":"frog"}]}
+/// ```
+///
+/// where `column` starts at 1 and counts bytes. Context lines have a `type`
+/// of `"context"` and no submatches.
+pub struct Printer<W> {
+  out: W,
+  options: PrintOptions,
+  path: String,
+  show_path: bool,
+  selected_in_file: usize,
+  selected: usize,
+  // The lines that may become the context before the next selected line
+  before: VecDeque<(usize, usize, String)>,
+  after_left: usize,
+  last_printed: Option<usize>,
+  printed_any: bool,
+}
+
+impl<W: Write> Printer<W> {
+  pub fn new(out: W, options: PrintOptions) -> Printer<W> {
+    Printer {
+      out,
+      options,
+      path: String::new(),
+      show_path: false,
+      selected_in_file: 0,
+      selected: 0,
+      before: VecDeque::new(),
+      after_left: 0,
+      last_printed: None,
+      printed_any: false,
+    }
+  }
+
+  /// Starts a new file, its path prefixes the lines if `show_path` is set.
+  /// Counts and JSON objects always say which file they are about.
+  pub fn begin_file(&mut self, path: &Path, show_path: bool) {
+    self.path = path.display().to_string();
+    self.show_path = show_path;
+    self.selected_in_file = 0;
+    self.before.clear();
+    self.after_left = 0;
+    self.last_printed = None;
+  }
+
+  /// Prints the counts or paths once the file has been searched.
+  pub fn end_file(&mut self) -> io::Result<()> {
+    match self.options.format {
+      Format::Count => {
+        if self.show_path {
+          let separator = self.paint(SEPARATOR_COLOR, ":");
+          write!(self.out, "{}{}", self.paint(PATH_COLOR, &self.path), separator)?;
+        }
+        writeln!(self.out, "{}", self.selected_in_file)
+      }
+      Format::FilesWithMatches if self.selected_in_file > 0 => {
+        let path = self.paint(PATH_COLOR, &self.path);
+        writeln!(self.out, "{}", path)
+      }
+      _ => Ok(()),
+    }
+  }
+
+  /// How many lines were selected in all the files.
+  pub fn selected(&self) -> usize {
+    self.selected
+  }
+
+  pub fn matched(&mut self, m: &LineMatch) -> io::Result<()> {
+    self.selected_in_file += 1;
+    self.selected += 1;
+    if self.summarizes() {
+      return Ok(());
+    }
+
+    let first = self.before.front().map_or(m.number, |(number, _, _)| *number);
+    self.separate(first)?;
+
+    while let Some((number, offset, line)) = self.before.pop_front() {
+      self.print(number, offset, &line, &[], '-')?;
+    }
+    self.print(m.number, m.offset, m.line, &m.spans, ':')?;
+    self.after_left = self.options.after;
+    Ok(())
+  }
+
+  /// A line that wasn't selected, printed only if it is close enough to one
+  /// that was.
+  pub fn context(&mut self, number: usize, offset: usize, line: &str) -> io::Result<()> {
+    if self.summarizes() {
+      return Ok(());
+    }
+    if self.after_left > 0 {
+      self.after_left -= 1;
+      return self.print(number, offset, line, &[], '-');
+    }
+    if self.options.before > 0 {
+      if self.before.len() == self.options.before {
+        self.before.pop_front();
+      }
+      self.before.push_back((number, offset, line.to_string()));
+    }
+    Ok(())
+  }
+
+  /// The writer, for output that doesn't go through the printer.
+  pub fn get_mut(&mut self) -> &mut W {
+    &mut self.out
+  }
+
+  pub fn into_inner(self) -> W {
+    self.out
+  }
+
+  fn separate(&mut self, next: usize) -> io::Result<()> {
+    let adjacent = self.last_printed.is_some_and(|last| last + 1 == next);
+    match self.options.group_separator() {
+      Some(separator) if self.printed_any && !adjacent => writeln!(self.out, "{}", separator),
+      _ => Ok(()),
+    }
+  }
+
+  // Counts and paths don't need the lines
+  fn summarizes(&self) -> bool {
+    matches!(self.options.format, Format::Count | Format::FilesWithMatches)
+  }
+
+  fn print(&mut self, number: usize, offset: usize, line: &str, spans: &[Range<usize>], separator: char) -> io::Result<()> {
+    self.last_printed = Some(number);
+    self.printed_any = true;
+    if self.options.format == Format::Json {
+      return self.print_json(number, offset, line, spans, separator == ':');
+    }
+
+    let separator = self.paint(SEPARATOR_COLOR, &separator.to_string());
+    if self.show_path {
+      write!(self.out, "{}{}", self.paint(PATH_COLOR, &self.path), separator)?;
+    }
+    if self.options.line_number {
+      write!(self.out, "{}{}", self.paint(NUMBER_COLOR, &number.to_string()), separator)?;
+    }
+    if self.options.byte_offset {
+      write!(self.out, "{}{}", self.paint(NUMBER_COLOR, &offset.to_string()), separator)?;
+    }
+
+    let mut written = 0;
+    let spans = if self.options.color { spans } else { &[] };
+    for span in spans {
+      write!(self.out, "{}{}{}{}", &line[written..span.start], MATCH_COLOR, &line[span.clone()], RESET)?;
+      written = span.end;
+    }
+    writeln!(self.out, "{}", &line[written..])
+  }
+
+  fn print_json(&mut self, number: usize, offset: usize, line: &str, spans: &[Range<usize>], matched: bool) -> io::Result<()> {
+    #[derive(Serialize)]
+    struct Submatch<'a> {
+      column: usize,
+      text: &'a str,
+    }
+
+    #[derive(Serialize)]
+    struct Line<'a> {
+      #[serde(rename = "type")]
+      kind: &'static str,
+      path: &'a str,
+      line_number: usize,
+      byte_offset: usize,
+      text: &'a str,
+      #[serde(skip_serializing_if = "Vec::is_empty")]
+      submatches: Vec<Submatch<'a>>,
+    }
+
+    let json = Line {
+      kind: if matched { "match" } else { "context" },
+      path: &self.path,
+      line_number: number,
+      byte_offset: offset,
+      text: line,
+      submatches: spans
+        .iter()
+        .map(|span| Submatch { column: span.start + 1, text: &line[span.clone()] })
+        .collect(),
+    };
+    serde_json::to_writer(&mut self.out, &json)?;
+    writeln!(self.out)
+  }
+
+  fn paint(&self, color: &str, text: &str) -> String {
+    paint(self.options.color, color, text)
+  }
+}
+
+fn paint(enabled: bool, color: &str, text: &str) -> String {
+  if enabled {
+    format!("{}{}{}", color, text, RESET)
+  } else {
+    text.to_string()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::matcher::Contains;
+  use crate::numbered_lines;
+
+  const POEM: &str = "\
+I'm nobody! Who are you?
+Are you nobody, too?
+Then there's a pair of us - don't tell!
+They'd banish us, you know.
+
+How dreary to be somebody!
+How public, like a frog
+To tell your name the livelong day
+To an admiring bog!";
+
+  fn print(query: &str, options: PrintOptions, path: Option<&Path>) -> String {
+    let mut printer = Printer::new(Vec::new(), options);
+    printer.begin_file(path.unwrap_or(Path::new("-")), path.is_some());
+    let matcher = Contains::new(query);
+    for (number, offset, line) in numbered_lines(POEM) {
+      match LineMatch::find(&matcher, number, offset, line) {
+        Some(m) => printer.matched(&m).unwrap(),
+        None => printer.context(number, offset, line).unwrap(),
+      }
+    }
+    printer.end_file().unwrap();
+    String::from_utf8(printer.into_inner()).unwrap()
+  }
+
+  #[test]
+  fn line_numbers_and_offsets() {
+    let options = PrintOptions { line_number: true, byte_offset: true, ..PrintOptions::default() };
+    assert_eq!("1:0:I'm nobody! Who are you?\n2:25:Are you nobody, too?\n", print("nobody", options, None));
+    assert_eq!("poem.txt:How public, like a frog\n", print("frog", PrintOptions::default(), Some(Path::new("poem.txt"))));
+  }
+
+  #[test]
+  fn context_groups() {
+    let options = PrintOptions { line_number: true, before: 1, after: 1, ..PrintOptions::default() };
+    assert_eq!(
+      "\
+2-Are you nobody, too?
+3:Then there's a pair of us - don't tell!
+4-They'd banish us, you know.
+--
+7-How public, like a frog
+8:To tell your name the livelong day
+9-To an admiring bog!
+",
+      print("tell", options, None)
+    );
+
+    // Overlapping contexts merge into one group
+    let options = PrintOptions { before: 2, ..PrintOptions::default() };
+    assert_eq!(
+      "\
+I'm nobody! Who are you?
+Are you nobody, too?
+Then there's a pair of us - don't tell!
+They'd banish us, you know.
+",
+      print("us", options, None)
+    );
+  }
+
+  #[test]
+  fn highlighting() {
+    let options = PrintOptions { color: true, ..PrintOptions::default() };
+    assert_eq!(
+      "\x1b[35mpoem.txt\x1b[0m\x1b[36m:\x1b[0mHow public, like a \x1b[1;31mfrog\x1b[0m\n",
+      print("frog", options.clone(), Some(Path::new("poem.txt")))
+    );
+
+    let mut printer = Printer::new(Vec::new(), options);
+    let m = LineMatch { number: 1, offset: 0, line: "a frog, a bog", spans: vec![2..6, 10..13] };
+    printer.matched(&m).unwrap();
+    assert_eq!("a \x1b[1;31mfrog\x1b[0m, a \x1b[1;31mbog\x1b[0m\n", String::from_utf8(printer.into_inner()).unwrap());
+  }
+
+  #[test]
+  fn json_lines() {
+    let options = PrintOptions { format: Format::Json, after: 1, ..PrintOptions::default() };
+    assert_eq!(
+      "\
+{\"type\":\"match\",\"path\":\"poem.txt\",\"line_number\":7,\"byte_offset\":142,\"text\":\"How public, like a frog\",\"submatches\":[{\"column\":20,\"text\":\"frog\"}]}
+{\"type\":\"context\",\"path\":\"poem.txt\",\"line_number\":8,\"byte_offset\":166,\"text\":\"To tell your name the livelong day\"}
+",
+      print("frog", options, Some(Path::new("poem.txt")))
+    );
+  }
+
+  #[test]
+  fn summaries() {
+    let count = PrintOptions { format: Format::Count, before: 3, ..PrintOptions::default() };
+    assert_eq!("2\n", print("To", count.clone(), None));
+    assert_eq!("poem.txt:0\n", print("toad", count, Some(Path::new("poem.txt"))));
+
+    let files = PrintOptions { format: Format::FilesWithMatches, ..PrintOptions::default() };
+    assert_eq!("poem.txt\n", print("To", files.clone(), Some(Path::new("poem.txt"))));
+    assert_eq!("", print("toad", files, Some(Path::new("poem.txt"))));
+  }
+}
@@ -0,0 +1,137 @@
+use std::io;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+
+/// Runs `work` on the jobs `produce` sends, on `threads` worker threads, and
+/// gives the results to `done` in the order the jobs were sent.
+///
+/// `produce` runs on a thread of its own so the workers can start while it
+/// is still finding jobs. It is held back once the results waiting for
+/// `done` reach a few per thread, so a slow job doesn't make the results of
+/// all the later ones pile up in memory. Stops as soon as `done` fails.
+pub fn run_ordered<J, R, P, W, D>(threads: usize, produce: P, work: W, mut done: D) -> io::Result<()>
+where
+  J: Send,
+  R: Send,
+  P: FnOnce(&mut dyn FnMut(J)) + Send,
+  W: Fn(J) -> R + Sync,
+  D: FnMut(R) -> io::Result<()>,
+{
+  let threads = threads.max(1);
+  let ahead = threads * 4;
+  // Bounded, so a fast producer doesn't queue up the whole tree
+  let (job_sender, job_receiver) = mpsc::sync_channel(ahead);
+  // Each job comes with the channel its result is sent on, and these
+  // channels wait here in the order of the jobs
+  let (order_sender, order_receiver) = mpsc::sync_channel::<mpsc::Receiver<R>>(ahead);
+  let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+  thread::scope(|scope| {
+    scope.spawn(move || {
+      produce(&mut |job| {
+        let (result_sender, result_receiver) = mpsc::sync_channel(1);
+        // Only fails once `done` has failed, the jobs don't matter anymore
+        if order_sender.send(result_receiver).is_ok() {
+          let _ = job_sender.send((job, result_sender));
+        }
+      });
+    });
+
+    for _ in 0..threads {
+      let job_receiver = Arc::clone(&job_receiver);
+      let work = &work;
+      scope.spawn(move || loop {
+        // The lock is released before working on the job
+        let job = job_receiver.lock().unwrap().recv();
+        let Ok((job, result_sender)) = job else { break };
+        let _ = result_sender.send(work(job));
+      });
+    }
+    drop(job_receiver);
+
+    for result_receiver in order_receiver {
+      // Only fails if the worker panicked, which the scope passes on
+      let Ok(result) = result_receiver.recv() else { break };
+      done(result)?;
+    }
+    Ok(())
+  })
+}
+
+/// The number of threads to use when asked for 0, one per CPU.
+pub fn default_threads() -> usize {
+  thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::atomic::AtomicUsize;
+  use std::sync::atomic::Ordering;
+  use std::time::Duration;
+
+  #[test]
+  fn keeps_the_order_of_the_jobs() {
+    let mut results = Vec::new();
+    run_ordered(
+      4,
+      |send| (0..20).for_each(send),
+      |i: u64| {
+        // Later jobs finish first
+        thread::sleep(Duration::from_millis(20 - i));
+        i * 2
+      },
+      |result| {
+        results.push(result);
+        Ok(())
+      },
+    )
+    .unwrap();
+    assert_eq!((0..20).map(|i| i * 2).collect::<Vec<_>>(), results);
+  }
+
+  #[test]
+  fn stops_when_done_fails() {
+    let mut count = 0;
+    let result = run_ordered(
+      2,
+      |send| (0..1000).for_each(send),
+      |i: u32| i,
+      |_| {
+        count += 1;
+        if count == 3 {
+          return Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"));
+        }
+        Ok(())
+      },
+    );
+    assert_eq!(io::ErrorKind::BrokenPipe, result.unwrap_err().kind());
+    assert_eq!(3, count);
+  }
+
+  #[test]
+  fn bounds_the_results_waiting() {
+    let started = AtomicUsize::new(0);
+    let mut most_ahead = 0;
+    run_ordered(
+      2,
+      |send| (0..100).for_each(send),
+      |i: usize| {
+        started.fetch_add(1, Ordering::SeqCst);
+        // The first job is slow, the other worker can only get so far ahead
+        if i == 0 {
+          thread::sleep(Duration::from_millis(100));
+        }
+        i
+      },
+      |i| {
+        most_ahead = most_ahead.max(started.load(Ordering::SeqCst) - i);
+        Ok(())
+      },
+    )
+    .unwrap();
+    assert!(most_ahead <= 2 * 4 + 2, "{} jobs ahead", most_ahead);
+  }
+}
@@ -0,0 +1,215 @@
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::matcher::Matcher;
+use crate::LineMatch;
+
+// Lines of context around each hunk of a diff
+const DIFF_CONTEXT: usize = 3;
+
+/// A line with its matches replaced, and where the replacements are in it.
+#[derive(Debug, PartialEq)]
+pub struct Replaced {
+  pub text: String,
+  pub spans: Vec<Range<usize>>,
+}
+
+/// Replaces the matches of a selected line. References to capture groups
+/// in the replacement, such as `$1` or `${name}`, are expanded by regex
+/// matchers.
+pub fn replace_line<M: Matcher + ?Sized>(matcher: &M, m: &LineMatch, replacement: &str) -> Replaced {
+  let mut text = String::with_capacity(m.line.len());
+  let mut spans = Vec::with_capacity(m.spans.len());
+  let mut copied = 0;
+  for span in &m.spans {
+    text.push_str(&m.line[copied..span.start]);
+    let start = text.len();
+    matcher.expand(m.line, span.clone(), replacement, &mut text);
+    spans.push(start..text.len());
+    copied = span.end;
+  }
+  text.push_str(&m.line[copied..]);
+  Replaced { text, spans }
+}
+
+/// A line of a file, with its line ending, and what replaces it.
+#[derive(Debug, PartialEq)]
+pub struct LineEdit<'a> {
+  pub old: &'a str,
+  /// `None` when the line doesn't change
+  pub new: Option<String>,
+}
+
+/// Replaces the matches of every line of the contents, keeping the line
+/// endings as they are.
+pub fn rewrite<'a, M: Matcher + ?Sized>(matcher: &M, contents: &'a str, replacement: &str) -> Vec<LineEdit<'a>> {
+  contents
+    .split_inclusive('\n')
+    .enumerate()
+    .map(|(i, old)| {
+      let body = old.trim_end_matches(['\r', '\n']);
+      let new = LineMatch::find(matcher, i + 1, 0, body)
+        .map(|m| replace_line(matcher, &m, replacement).text + &old[body.len()..])
+        .filter(|new| new != old);
+      LineEdit { old, new }
+    })
+    .collect()
+}
+
+/// How many lines changed.
+pub fn changes(edits: &[LineEdit]) -> usize {
+  edits.iter().filter(|edit| edit.new.is_some()).count()
+}
+
+/// The contents once edited.
+pub fn edited(edits: &[LineEdit]) -> String {
+  edits.iter().map(|edit| edit.new.as_deref().unwrap_or(edit.old)).collect()
+}
+
+/// The edits as a unified diff, empty when nothing changes.
+pub fn unified_diff(path: &str, edits: &[LineEdit]) -> String {
+  let changed: Vec<usize> = (0..edits.len()).filter(|&i| edits[i].new.is_some()).collect();
+  if changed.is_empty() {
+    return String::new();
+  }
+
+  // The line number each old line starts at in the new file, replacements
+  // can have several lines
+  let mut new_numbers = Vec::with_capacity(edits.len());
+  let mut number = 1;
+  for edit in edits {
+    new_numbers.push(number);
+    number += edit.new.as_deref().map_or(1, |new| new.split_inclusive('\n').count());
+  }
+
+  let path = path.trim_start_matches('/');
+  let mut diff = format!("--- a/{}\n+++ b/{}\n", path, path);
+  let mut hunk_start = 0;
+  while hunk_start < changed.len() {
+    // Changes close enough to share their context go in the same hunk
+    let mut hunk_end = hunk_start;
+    while hunk_end + 1 < changed.len() && changed[hunk_end + 1] - changed[hunk_end] <= 2 * DIFF_CONTEXT + 1 {
+      hunk_end += 1;
+    }
+    let first = changed[hunk_start].saturating_sub(DIFF_CONTEXT);
+    let last = (changed[hunk_end] + DIFF_CONTEXT).min(edits.len() - 1);
+
+    let new_count: usize = (first..=last)
+      .map(|i| edits[i].new.as_deref().map_or(1, |new| new.split_inclusive('\n').count()))
+      .sum();
+    diff.push_str(&format!("@@ -{},{} +{},{} @@\n", first + 1, last - first + 1, new_numbers[first], new_count));
+    for edit in &edits[first..=last] {
+      match &edit.new {
+        None => push_diff_lines(&mut diff, ' ', edit.old),
+        Some(new) => {
+          push_diff_lines(&mut diff, '-', edit.old);
+          push_diff_lines(&mut diff, '+', new);
+        }
+      }
+    }
+    hunk_start = hunk_end + 1;
+  }
+  diff
+}
+
+fn push_diff_lines(diff: &mut String, prefix: char, lines: &str) {
+  for line in lines.split_inclusive('\n') {
+    diff.push(prefix);
+    diff.push_str(line);
+    if !line.ends_with('\n') {
+      diff.push_str("\n\\ No newline at end of file\n");
+    }
+  }
+}
+
+/// Replaces the file with the contents, writing them to a temporary file
+/// next to it first and renaming that over the file, so the file is never
+/// seen half written.
+pub fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+  let name = path
+    .file_name()
+    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?;
+  let temp = path.with_file_name(format!(".{}.minigrep-{}.tmp", name.to_string_lossy(), std::process::id()));
+
+  let result = fs::write(&temp, contents)
+    .and_then(|_| fs::set_permissions(&temp, fs::metadata(path)?.permissions()))
+    .and_then(|_| fs::rename(&temp, path));
+  if result.is_err() {
+    let _ = fs::remove_file(&temp);
+  }
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::matcher::Contains;
+  use crate::matcher::RegexMatcher;
+
+  #[test]
+  fn replaces_matches() {
+    let matcher = Contains::new("frog");
+    let m = LineMatch::find(&matcher, 1, 0, "a frog, a frog").unwrap();
+    let replaced = replace_line(&matcher, &m, "toad");
+    assert_eq!("a toad, a toad", replaced.text);
+    assert_eq!(vec![2..6, 10..14], replaced.spans);
+
+    let matcher = RegexMatcher::new(r"(?P<adj>\w+) (\w+)", true).unwrap();
+    let m = LineMatch::find(&matcher, 1, 0, "public frog").unwrap();
+    assert_eq!("frog is public!", replace_line(&matcher, &m, "$2 is ${adj}!").text);
+  }
+
+  #[test]
+  fn rewrites_keeping_line_endings() {
+    let contents = "a frog\r\nno toad\nfrog";
+    let edits = rewrite(&Contains::new("frog"), contents, "toad");
+    assert_eq!(2, changes(&edits));
+    assert_eq!("a toad\r\nno toad\ntoad", edited(&edits));
+  }
+
+  #[test]
+  fn diffs() {
+    let contents: String = (1..=12).map(|i| format!("line {}\n", i)).collect();
+    let contents = contents + "frog";
+    let edits = rewrite(&RegexMatcher::new("^line (1|2|11)$", true).unwrap(), &contents, "changed\nline $1");
+    assert_eq!(
+      "\
+--- a/poem.txt
++++ b/poem.txt
+@@ -1,5 +1,7 @@
+-line 1
++changed
++line 1
+-line 2
++changed
++line 2
+ line 3
+ line 4
+ line 5
+@@ -8,6 +10,7 @@
+ line 8
+ line 9
+ line 10
+-line 11
++changed
++line 11
+ line 12
+ frog
+\\ No newline at end of file
+",
+      unified_diff("poem.txt", &edits)
+    );
+    assert_eq!("", unified_diff("poem.txt", &rewrite(&Contains::new("toad"), &contents, "frog")));
+  }
+
+  #[test]
+  fn writes_atomically() {
+    let path = std::env::temp_dir().join(format!("minigrep_replace_{}.txt", std::process::id()));
+    fs::write(&path, "frog").unwrap();
+    write_atomically(&path, "toad").unwrap();
+    assert_eq!("toad", fs::read_to_string(&path).unwrap());
+    fs::remove_file(path).unwrap();
+  }
+}
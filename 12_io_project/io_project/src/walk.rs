@@ -0,0 +1,429 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// A shell-like pattern: `*` and `?` stop at `/`, `**` doesn't and `[a-z]`
+/// or `[!a-z]` match a class of characters.
+///
+/// Without a `/` the pattern applies to the file name only, otherwise to the
+/// whole relative path.
+#[derive(Debug, Clone)]
+pub struct Glob {
+  tokens: Vec<Token>,
+  has_slash: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  Char(char),
+  Any,
+  Star,
+  DoubleStar,
+  // `**/`, zero or more directories
+  Directories,
+  Class { ranges: Vec<(char, char)>, negated: bool },
+}
+
+impl Glob {
+  pub fn new(pattern: &str) -> Glob {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+      match chars[i] {
+        '*' if chars.get(i + 1) == Some(&'*') => {
+          if chars.get(i + 2) == Some(&'/') {
+            tokens.push(Token::Directories);
+            i += 1;
+          } else {
+            tokens.push(Token::DoubleStar);
+          }
+          i += 1;
+        }
+        '*' => tokens.push(Token::Star),
+        '?' => tokens.push(Token::Any),
+        '[' => match parse_class(&chars[i + 1..]) {
+          Some((token, length)) => {
+            tokens.push(token);
+            i += length;
+          }
+          // An unclosed bracket is a plain character
+          None => tokens.push(Token::Char('[')),
+        },
+        '\\' if i + 1 < chars.len() => {
+          tokens.push(Token::Char(chars[i + 1]));
+          i += 1;
+        }
+        c => tokens.push(Token::Char(c)),
+      }
+      i += 1;
+    }
+    Glob { tokens, has_slash: pattern.contains('/') }
+  }
+
+  /// Matches a path relative to where the pattern applies.
+  pub fn matches(&self, path: &Path) -> bool {
+    if self.has_slash {
+      self.matches_str(&to_slashes(path))
+    } else {
+      path
+        .file_name()
+        .is_some_and(|name| self.matches_str(&name.to_string_lossy()))
+    }
+  }
+
+  fn matches_str(&self, text: &str) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    let (n, m) = (self.tokens.len(), text.len());
+    // matched[i][j] is whether tokens[i..] match text[j..]
+    let mut matched = vec![vec![false; m + 1]; n + 1];
+    matched[n][m] = true;
+
+    for i in (0..n).rev() {
+      // Whether a directory separator at or after j is followed by a match
+      let mut separator_then_match = false;
+      for j in (0..=m).rev() {
+        let next = j < m && matched[i + 1][j + 1];
+        matched[i][j] = match &self.tokens[i] {
+          Token::Char(c) => next && text[j] == *c,
+          Token::Any => next && text[j] != '/',
+          Token::Class { ranges, negated } => {
+            next && text[j] != '/' && ranges.iter().any(|&(a, b)| a <= text[j] && text[j] <= b) != *negated
+          }
+          Token::Star => matched[i + 1][j] || (j < m && text[j] != '/' && matched[i][j + 1]),
+          Token::DoubleStar => matched[i + 1][j] || (j < m && matched[i][j + 1]),
+          Token::Directories => {
+            separator_then_match |= next && text[j] == '/';
+            matched[i + 1][j] || separator_then_match
+          }
+        };
+      }
+    }
+    matched[0][0]
+  }
+}
+
+// Parses what follows `[`, returns the class and how many characters it used
+fn parse_class(chars: &[char]) -> Option<(Token, usize)> {
+  let negated = matches!(chars.first(), Some('!') | Some('^'));
+  let mut i = usize::from(negated);
+  let mut ranges = Vec::new();
+  // A `]` right after the opening bracket is part of the class
+  while i < chars.len() && (chars[i] != ']' || i == usize::from(negated)) {
+    if i + 2 < chars.len() && chars[i + 1] == '-' && chars[i + 2] != ']' {
+      ranges.push((chars[i], chars[i + 2]));
+      i += 3;
+    } else {
+      ranges.push((chars[i], chars[i]));
+      i += 1;
+    }
+  }
+  if i == chars.len() {
+    return None;
+  }
+  Some((Token::Class { ranges, negated }, i + 1))
+}
+
+fn to_slashes(path: &Path) -> String {
+  let components: Vec<_> = path.components().map(|c| c.as_os_str().to_string_lossy()).collect();
+  components.join("/")
+}
+
+/// The rules of the `.gitignore` and `.ignore` files of a directory.
+#[derive(Debug)]
+pub struct Ignore {
+  dir: PathBuf,
+  rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+  glob: Glob,
+  negated: bool,
+  dir_only: bool,
+}
+
+impl Ignore {
+  pub fn parse(dir: &Path, contents: &str) -> Ignore {
+    let mut rules = Vec::new();
+    for line in contents.lines() {
+      let line = line.trim_end();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let (negated, pattern) = match line.strip_prefix('!') {
+        Some(pattern) => (true, pattern),
+        None => (false, line.strip_prefix('\\').unwrap_or(line)),
+      };
+      let (dir_only, pattern) = match pattern.strip_suffix('/') {
+        Some(pattern) => (true, pattern),
+        None => (false, pattern),
+      };
+      // A slash anywhere but at the end anchors the pattern to this directory
+      let glob = match pattern.strip_prefix('/') {
+        Some(anchored) => Glob { has_slash: true, ..Glob::new(anchored) },
+        None => Glob::new(pattern),
+      };
+      rules.push(Rule { glob, negated, dir_only });
+    }
+    Ignore { dir: dir.to_path_buf(), rules }
+  }
+
+  /// Reads the ignore files of the directory, if it has any.
+  pub fn from_dir(dir: &Path) -> Option<Ignore> {
+    let contents: Vec<String> = IGNORE_FILES
+      .iter()
+      .filter_map(|name| fs::read_to_string(dir.join(name)).ok())
+      .collect();
+    if contents.is_empty() {
+      None
+    } else {
+      Some(Ignore::parse(dir, &contents.join("\n")))
+    }
+  }
+
+  /// Returns whether the last rule matching the path ignores it, or `None`
+  /// if no rule matches.
+  pub fn matched(&self, path: &Path, is_dir: bool) -> Option<bool> {
+    let relative = path.strip_prefix(&self.dir).ok()?;
+    self
+      .rules
+      .iter()
+      .rev()
+      .find(|rule| (is_dir || !rule.dir_only) && rule.glob.matches(relative))
+      .map(|rule| !rule.negated)
+  }
+}
+
+/// Include and exclude globs, relative to the path given on the command line.
+#[derive(Debug, Default)]
+pub struct Filter {
+  include: Vec<Glob>,
+  exclude: Vec<Glob>,
+}
+
+impl Filter {
+  pub fn new(include: &[String], exclude: &[String]) -> Filter {
+    Filter {
+      include: include.iter().map(|pattern| Glob::new(pattern)).collect(),
+      exclude: exclude.iter().map(|pattern| Glob::new(pattern)).collect(),
+    }
+  }
+
+  /// Files have to match an include glob, when there are some, and no
+  /// exclude glob. Directories only have to match no exclude glob.
+  pub fn allows(&self, path: &Path, is_dir: bool) -> bool {
+    let included = is_dir || self.include.is_empty() || self.include.iter().any(|glob| glob.matches(path));
+    included && !self.exclude.iter().any(|glob| glob.matches(path))
+  }
+}
+
+#[derive(Debug)]
+pub struct WalkError {
+  pub path: PathBuf,
+  pub error: io::Error,
+}
+
+impl fmt::Display for WalkError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}: {}", self.path.display(), self.error)
+  }
+}
+
+/// Iterates over the files of the paths, going through directories
+/// recursively in alphabetical order.
+///
+/// Paths given explicitly are always kept, the ones found in directories are
+/// skipped when an ignore file or the filter says so. Symbolic links found
+/// in directories aren't followed.
+pub struct Walk {
+  filter: Filter,
+  pending: Vec<Pending>,
+}
+
+struct Pending {
+  path: PathBuf,
+  // The path given on the command line this one was found in
+  root: Rc<PathBuf>,
+  ignores: Vec<Rc<Ignore>>,
+}
+
+impl Walk {
+  pub fn new<P: AsRef<Path>>(paths: &[P], filter: Filter) -> Walk {
+    let pending = paths
+      .iter()
+      .rev()
+      .map(|path| Pending {
+        path: path.as_ref().to_path_buf(),
+        root: Rc::new(path.as_ref().to_path_buf()),
+        ignores: Vec::new(),
+      })
+      .collect();
+    Walk { filter, pending }
+  }
+
+  fn push_children(&mut self, dir: Pending) -> io::Result<()> {
+    let mut ignores = dir.ignores;
+    if let Some(ignore) = Ignore::from_dir(&dir.path) {
+      ignores.push(Rc::new(ignore));
+    }
+
+    let mut children = Vec::new();
+    for entry in fs::read_dir(&dir.path)? {
+      let entry = entry?;
+      let file_type = entry.file_type()?;
+      if file_type.is_symlink() || entry.file_name() == ".git" {
+        continue;
+      }
+      children.push((entry.path(), file_type.is_dir()));
+    }
+    children.sort();
+
+    for (path, is_dir) in children.into_iter().rev() {
+      // The deepest ignore file that has an opinion wins
+      let ignored = ignores.iter().rev().find_map(|ignore| ignore.matched(&path, is_dir));
+      let relative = path.strip_prefix(&*dir.root).unwrap_or(&path);
+      if ignored == Some(true) || !self.filter.allows(relative, is_dir) {
+        continue;
+      }
+      self.pending.push(Pending {
+        path,
+        root: Rc::clone(&dir.root),
+        ignores: ignores.clone(),
+      });
+    }
+    Ok(())
+  }
+}
+
+impl Iterator for Walk {
+  type Item = Result<PathBuf, WalkError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    while let Some(pending) = self.pending.pop() {
+      let is_dir = match fs::metadata(&pending.path) {
+        Ok(metadata) => metadata.is_dir(),
+        Err(error) => return Some(Err(WalkError { path: pending.path, error })),
+      };
+      if !is_dir {
+        return Some(Ok(pending.path));
+      }
+
+      let path = pending.path.clone();
+      if let Err(error) = self.push_children(pending) {
+        return Some(Err(WalkError { path, error }));
+      }
+    }
+    None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn glob(pattern: &str, path: &str) -> bool {
+    Glob::new(pattern).matches(Path::new(path))
+  }
+
+  #[test]
+  fn globs() {
+    assert!(glob("*.rs", "src/lib.rs"));
+    assert!(!glob("*.rs", "src/lib.rs.bak"));
+    assert!(glob("src/*.rs", "src/lib.rs"));
+    assert!(!glob("src/*.rs", "src/walk/mod.rs"));
+    assert!(glob("src/**/*.rs", "src/walk/deep/mod.rs"));
+    assert!(glob("src/**/*.rs", "src/lib.rs"));
+    assert!(glob("**/target", "target"));
+    assert!(glob("src/**", "src/a/b"));
+    assert!(glob("poem.tx?", "poem.txt"));
+    assert!(glob("[a-c]*.txt", "b.txt"));
+    assert!(!glob("[!a-c]*.txt", "b.txt"));
+    assert!(glob("[]]", "]"));
+    assert!(glob("[abc", "[abc"));
+    assert!(glob("\\*", "*"));
+    assert!(!glob("\\*", "a"));
+  }
+
+  #[test]
+  fn ignore_rules() {
+    let ignore = Ignore::parse(Path::new("repo"), "# Build output\n/target/\n*.log\n!keep.log\ndocs/*.tmp\n");
+    let ignored = |path: &str, is_dir: bool| ignore.matched(Path::new(path), is_dir);
+
+    assert_eq!(Some(true), ignored("repo/target", true));
+    // Anchored to the directory of the ignore file, and only for directories
+    assert_eq!(None, ignored("repo/src/target", true));
+    assert_eq!(None, ignored("repo/target", false));
+    assert_eq!(Some(true), ignored("repo/src/debug.log", false));
+    assert_eq!(Some(false), ignored("repo/keep.log", false));
+    assert_eq!(Some(true), ignored("repo/docs/a.tmp", false));
+    assert_eq!(None, ignored("repo/src/docs/a.tmp", false));
+    assert_eq!(None, ignored("elsewhere/debug.log", false));
+  }
+
+  fn tree(name: &str, files: &[&str]) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("minigrep_walk_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    for file in files {
+      let (path, contents) = file.split_once('=').unwrap_or((file, "text"));
+      let path = root.join(path);
+      fs::create_dir_all(path.parent().unwrap()).unwrap();
+      fs::write(path, contents.replace("\\n", "\n")).unwrap();
+    }
+    root
+  }
+
+  fn walk(root: &Path, path: &Path, filter: Filter) -> Vec<String> {
+    Walk::new(&[path], filter)
+      .map(|path| to_slashes(path.unwrap().strip_prefix(root).unwrap()))
+      .collect()
+  }
+
+  #[test]
+  fn walks_recursively_and_honours_ignore_files() {
+    let root = tree(
+      "ignore",
+      &[
+        ".gitignore=*.log\\n/build/\\n",
+        "a.txt",
+        "b.log",
+        "build/out.txt",
+        "src/.ignore=!keep.log\\ngenerated/",
+        "src/keep.log",
+        "src/main.rs",
+        "src/generated/code.rs",
+        ".git/HEAD",
+      ],
+    );
+
+    assert_eq!(
+      vec![".gitignore", "a.txt", "src/.ignore", "src/keep.log", "src/main.rs"],
+      walk(&root, &root, Filter::default())
+    );
+    // Explicit paths are kept even when ignored
+    assert_eq!(vec!["b.log"], walk(&root, &root.join("b.log"), Filter::default()));
+
+    fs::remove_dir_all(root).unwrap();
+  }
+
+  #[test]
+  fn filters_with_globs() {
+    let root = tree("filter", &["a.txt", "b.rs", "src/c.rs", "target/d.rs"]);
+
+    let filter = Filter::new(&[String::from("*.rs")], &[String::from("target")]);
+    assert_eq!(vec!["b.rs", "src/c.rs"], walk(&root, &root, filter));
+
+    let filter = Filter::new(&[String::from("src/*.rs")], &[]);
+    assert_eq!(vec!["src/c.rs"], walk(&root, &root, filter));
+
+    let errors: Vec<_> = Walk::new(&[root.join("missing")], Filter::default()).collect();
+    assert!(errors[0].as_ref().unwrap_err().to_string().contains("missing"));
+
+    fs::remove_dir_all(root).unwrap();
+  }
+}
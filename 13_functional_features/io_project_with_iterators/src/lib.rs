@@ -2,41 +2,100 @@ use std::error::Error;
 use std::fs;
 use std::env;
 
+pub use io_project::matcher;
+
+use matcher::AnyOf;
+use matcher::Contains;
+use matcher::ContainsIgnoreCase;
+use matcher::Invert;
+use matcher::Matcher;
+use matcher::RegexMatcher;
+use matcher::WholeWord;
+
 pub struct Config {
-  pub query: String,
+  pub patterns: Vec<String>,
   pub filename: String,
   pub case_sensitive: bool,
+  pub regex: bool,
+  pub whole_word: bool,
+  pub invert: bool,
 }
 
 impl Config {
+  /*
+    minigrep [--regex] [-w] [-v] [-e pattern]... [pattern] filename
+    -e can be repeated to search for several patterns at once
+  */
   pub fn new(mut args: std::env::Args) -> Result<Config, &'static str> {
     args.next();
 
-    let query = match args.next() {
-        Some(arg) => arg,
+    let mut patterns = Vec::new();
+    let mut positional = Vec::new();
+    let mut regex = false;
+    let mut whole_word = false;
+    let mut invert = false;
+
+    while let Some(arg) = args.next() {
+      match arg.as_str() {
+        "--regex" => regex = true,
+        "-w" => whole_word = true,
+        "-v" => invert = true,
+        "-e" => match args.next() {
+          Some(pattern) => patterns.push(pattern),
+          None => return Err("Didn't get a pattern after -e"),
+        },
+        _ => positional.push(arg),
+      }
+    }
+
+    let mut positional = positional.into_iter();
+    if patterns.is_empty() {
+      match positional.next() {
+        Some(arg) => patterns.push(arg),
         None => return Err("Didn't get a query string"),
-    };
+      }
+    }
 
-    let filename = match args.next() {
+    let filename = match positional.next() {
         Some(arg) => arg,
         None => return Err("Didn't get a file name"),
     };
     let case_sensitive = env::var("CASE_INSENSITIVE").is_err();
 
-    Ok(Config { query, filename, case_sensitive })
+    Ok(Config { patterns, filename, case_sensitive, regex, whole_word, invert })
+  }
+
+  /// Builds the matcher selecting the lines to print.
+  pub fn matcher(&self) -> Result<Box<dyn Matcher>, regex::Error> {
+    let mut matchers = self
+      .patterns
+      .iter()
+      .map(|pattern| {
+        let matcher: Box<dyn Matcher> = if self.regex {
+          Box::new(RegexMatcher::new(pattern, self.case_sensitive)?)
+        } else if self.case_sensitive {
+          Box::new(Contains::new(pattern))
+        } else {
+          Box::new(ContainsIgnoreCase::new(pattern))
+        };
+        Ok(if self.whole_word { Box::new(WholeWord::new(matcher)) } else { matcher })
+      })
+      .collect::<Result<Vec<Box<dyn Matcher>>, regex::Error>>()?;
+
+    let matcher = if matchers.len() == 1 {
+      matchers.pop().unwrap()
+    } else {
+      Box::new(AnyOf::new(matchers))
+    };
+    Ok(if self.invert { Box::new(Invert::new(matcher)) } else { matcher })
   }
 }
 
 pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-  let contents = fs::read_to_string(config.filename)?;
+  let contents = fs::read_to_string(&config.filename)?;
+  let matcher = config.matcher()?;
 
-  let results = if config.case_sensitive {
-    search(&config.query, &contents)
-  } else {
-    search_case_insensitive(&config.query, &contents)
-  };
-
-  for line in results {
+  for line in search_with(&matcher, &contents) {
     println!("{}", line);
   }
 
@@ -44,24 +103,20 @@ pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
 }
 
 pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-  contents
-    .lines()
-    .filter(|line| line.contains(query))
-    .collect()
+  search_with(&Contains::new(query), contents)
 }
 
 pub fn search_case_insensitive<'a>(
   query: &str,
   contents: &'a str,
 ) -> Vec<&'a str> {
-  let query = query.to_lowercase();
-  let mut results = Vec::new();
-
-  for line in contents.lines() {
-    if line.to_lowercase().contains(&query) {
-      results.push(line);
-    }
-  }
+  search_with(&ContainsIgnoreCase::new(query), contents)
+}
 
-  results
+/// Returns the lines selected by the matcher.
+pub fn search_with<'a, M: Matcher + ?Sized>(matcher: &M, contents: &'a str) -> Vec<&'a str> {
+  contents
+    .lines()
+    .filter(|line| matcher.is_match(line))
+    .collect()
 }
@@ -0,0 +1,642 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::clock::Clock;
+use crate::repository::PostRecord;
+use crate::repository::PostRepository;
+use crate::repository::RepositoryError;
+use crate::repository::StateRecord;
+use crate::site;
+use crate::Change;
+use crate::Post;
+use crate::TransitionError;
+
+const STATES: &[&str] = &["draft", "pending_review", "scheduled", "published"];
+
+pub const USAGE: &str = "\
+Usage: blog COMMAND [ARG]...
+Manages the posts kept in $BLOG_DIR, ./posts by default.
+
+Commands:
+  new ID [--author NAME] [--quorum N] [--title TITLE] [--tags TAG,...]
+                                       Start a draft, N reviewers have to approve it
+  edit ID TEXT                         Add TEXT to a draft
+  schedule ID TIMESTAMP                Publish a draft at TIMESTAMP, in seconds since the
+                                       Unix epoch, once approved
+  submit ID                            Ask for a review of a draft
+  approve ID REVIEWER                  Approve a post pending review, which publishes it once
+                                       the quorum is reached
+  reject ID REVIEWER                   Send a post pending review back to its author
+  publish ID                           Publish a scheduled post now rather than at its time
+  read ID                              Print the content of a post once readers can see it
+  list [--state STATE]                 List the posts, only the ones in STATE if given
+  show ID                              Print a post with its history
+  export DIR                           Write the published posts to DIR as a static site
+  help                                 Print this help
+
+STATE is draft, pending_review, scheduled or published.";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+  New {
+    id: String,
+    author: String,
+    quorum: usize,
+    title: String,
+    tags: Vec<String>,
+  },
+  Edit {
+    id: String,
+    text: String,
+  },
+  Schedule {
+    id: String,
+    at: u64,
+  },
+  Submit {
+    id: String,
+  },
+  Approve {
+    id: String,
+    reviewer: String,
+  },
+  Reject {
+    id: String,
+    reviewer: String,
+  },
+  Publish {
+    id: String,
+  },
+  Read {
+    id: String,
+  },
+  List {
+    state: Option<String>,
+  },
+  Show {
+    id: String,
+  },
+  Export {
+    dir: PathBuf,
+  },
+  Help,
+}
+
+#[derive(Debug)]
+pub enum CliError {
+  Usage(String),
+  Exists(String),
+  // The action had no effect on the post
+  Transition { id: String, error: TransitionError },
+  // Readers can't see the post yet
+  NotVisible { id: String, state: &'static str },
+  Repository(RepositoryError),
+  Io(io::Error),
+}
+
+impl fmt::Display for CliError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      CliError::Usage(message) => write!(f, "{}", message),
+      CliError::Exists(id) => write!(f, "post {:?} already exists", id),
+      CliError::Transition {
+        id,
+        error: TransitionError::NotAllowed { state, action },
+      } => write!(
+        f,
+        "can't {} post {:?}: it is {}",
+        action,
+        id,
+        state.replace('_', " ")
+      ),
+      CliError::Transition {
+        id,
+        error: TransitionError::AlreadyApproved { reviewer },
+      } => write!(f, "{} already approved post {:?}", reviewer, id),
+      CliError::NotVisible { id, state } => write!(
+        f,
+        "post {:?} can't be read yet: it is {}",
+        id,
+        state.replace('_', " ")
+      ),
+      CliError::Repository(e) => write!(f, "{}", e),
+      CliError::Io(e) => write!(f, "{}", e),
+    }
+  }
+}
+
+impl Error for CliError {}
+
+impl From<RepositoryError> for CliError {
+  fn from(e: RepositoryError) -> CliError {
+    CliError::Repository(e)
+  }
+}
+
+impl From<io::Error> for CliError {
+  fn from(e: io::Error) -> CliError {
+    CliError::Io(e)
+  }
+}
+
+// Parses the arguments, without the program name
+pub fn parse(args: &[String]) -> Result<Command, CliError> {
+  let (command, args) = match args.split_first() {
+    Some((command, args)) => (command.as_str(), args),
+    None => return Err(CliError::Usage(String::from("no command"))),
+  };
+  let mut positional = Vec::new();
+  let mut options = Vec::new();
+  let mut args = args.iter();
+  while let Some(arg) = args.next() {
+    if let Some(name) = arg.strip_prefix("--") {
+      let value = args
+        .next()
+        .ok_or_else(|| CliError::Usage(format!("{} needs a value", arg)))?;
+      options.push((name, value.clone()));
+    } else {
+      positional.push(arg.clone());
+    }
+  }
+
+  let allowed: &[&str] = match command {
+    "new" => &["author", "quorum", "title", "tags"],
+    "list" => &["state"],
+    _ => &[],
+  };
+  if let Some((name, _)) = options.iter().find(|(name, _)| !allowed.contains(name)) {
+    return Err(CliError::Usage(format!(
+      "{} doesn't take --{}",
+      command, name
+    )));
+  }
+  let option = |name: &str| {
+    options
+      .iter()
+      .rev()
+      .find(|(option, _)| *option == name)
+      .map(|(_, value)| value.clone())
+  };
+
+  let names: &[&str] = match command {
+    "new" | "submit" | "publish" | "read" | "show" => &["ID"],
+    "export" => &["DIR"],
+    "edit" => &["ID", "TEXT"],
+    "approve" | "reject" => &["ID", "REVIEWER"],
+    "schedule" => &["ID", "TIMESTAMP"],
+    "list" | "help" => &[],
+    _ => return Err(CliError::Usage(format!("unknown command {}", command))),
+  };
+  if positional.len() != names.len() {
+    return Err(CliError::Usage(format!(
+      "{} takes {}",
+      command,
+      if names.is_empty() {
+        String::from("no arguments")
+      } else {
+        names.join(" ")
+      }
+    )));
+  }
+  let mut positional = positional.into_iter();
+  let mut next = || positional.next().unwrap();
+
+  Ok(match command {
+    "new" => {
+      let quorum = match option("quorum") {
+        Some(quorum) => quorum
+          .parse()
+          .ok()
+          .filter(|&quorum| quorum > 0)
+          .ok_or_else(|| CliError::Usage(format!("invalid quorum {:?}", quorum)))?,
+        None => 1,
+      };
+      Command::New {
+        id: next(),
+        author: option("author").unwrap_or_else(|| String::from("anonymous")),
+        quorum,
+        title: option("title").unwrap_or_default(),
+        tags: option("tags")
+          .map(|tags| {
+            tags
+              .split(',')
+              .map(|tag| tag.trim().to_string())
+              .filter(|tag| !tag.is_empty())
+              .collect()
+          })
+          .unwrap_or_default(),
+      }
+    }
+    "edit" => Command::Edit {
+      id: next(),
+      text: next(),
+    },
+    "schedule" => {
+      let id = next();
+      let at = next();
+      Command::Schedule {
+        id,
+        at: at
+          .parse()
+          .map_err(|_| CliError::Usage(format!("invalid timestamp {:?}", at)))?,
+      }
+    }
+    "submit" => Command::Submit { id: next() },
+    "approve" => Command::Approve {
+      id: next(),
+      reviewer: next(),
+    },
+    "reject" => Command::Reject {
+      id: next(),
+      reviewer: next(),
+    },
+    "publish" => Command::Publish { id: next() },
+    "read" => Command::Read { id: next() },
+    "list" => {
+      let state = option("state");
+      if let Some(state) = &state {
+        if !STATES.contains(&state.as_str()) {
+          return Err(CliError::Usage(format!("unknown state {}", state)));
+        }
+      }
+      Command::List { state }
+    }
+    "show" => Command::Show { id: next() },
+    "export" => Command::Export {
+      dir: PathBuf::from(next()),
+    },
+    _ => Command::Help,
+  })
+}
+
+// Runs the command on the posts of the repository, printing to `out`. The
+// posts tell the time with `clock`.
+pub fn run<R, C, W>(
+  repository: &mut R,
+  clock: &C,
+  command: Command,
+  out: &mut W,
+) -> Result<(), CliError>
+where
+  R: PostRepository,
+  C: Clock + Clone + 'static,
+  W: Write,
+{
+  match command {
+    Command::New {
+      id,
+      author,
+      quorum,
+      title,
+      tags,
+    } => {
+      match repository.load(&id) {
+        Err(RepositoryError::NotFound(_)) => {}
+        Err(e) => return Err(e.into()),
+        Ok(_) => return Err(CliError::Exists(id)),
+      }
+      let mut post = Post::written_by(&author, quorum);
+      post.set_clock(clock.clone());
+      post.set_title(&title);
+      post.set_tags(&tags.iter().map(String::as_str).collect::<Vec<_>>());
+      repository.save(&id, &post)?;
+      writeln!(out, "created draft {}", id)?;
+    }
+    Command::Edit { id, text } => {
+      transition(repository, clock, &id, |post| post.try_add_text(&text))?;
+      writeln!(out, "edited {}", id)?;
+    }
+    Command::Schedule { id, at } => {
+      transition(repository, clock, &id, |post| post.try_schedule(at))?;
+      writeln!(out, "{} will be published on {}", id, site::format_date(at))?;
+    }
+    Command::Submit { id } => {
+      transition(repository, clock, &id, |post| post.try_request_review())?;
+      writeln!(out, "submitted {} for review", id)?;
+    }
+    Command::Approve { id, reviewer } => {
+      let post = transition(repository, clock, &id, |post| post.try_approve(&reviewer))?;
+      let record = PostRecord::from(&post);
+      match record.state {
+        StateRecord::PendingReview { approvals } => writeln!(
+          out,
+          "approved {}, {} of {} approvals",
+          id,
+          approvals.len(),
+          record.quorum
+        )?,
+        StateRecord::Scheduled { at } => writeln!(
+          out,
+          "approved {}, scheduled for {}",
+          id,
+          site::format_date(at)
+        )?,
+        _ => writeln!(out, "approved and published {}", id)?,
+      }
+    }
+    Command::Reject { id, reviewer } => {
+      transition(repository, clock, &id, |post| post.try_reject(&reviewer))?;
+      writeln!(out, "sent {} back to its author", id)?;
+    }
+    Command::Publish { id } => {
+      transition(repository, clock, &id, |post| post.try_publish())?;
+      writeln!(out, "published {}", id)?;
+    }
+    Command::Read { id } => {
+      let post = load(repository, clock, &id)?;
+      if !post.is_visible() {
+        return Err(CliError::NotVisible {
+          id,
+          state: post.state_name(),
+        });
+      }
+      writeln!(out, "{}", post.content())?;
+    }
+    Command::List { state } => {
+      for id in repository.ids()? {
+        let name = load(repository, clock, &id)?.state_name();
+        if state.as_deref().map_or(true, |state| state == name) {
+          writeln!(out, "{}\t{}", id, name)?;
+        }
+      }
+    }
+    Command::Show { id } => {
+      let post = load(repository, clock, &id)?;
+      let record = PostRecord::from(&post);
+      writeln!(out, "id: {}", id)?;
+      writeln!(out, "author: {}", record.author)?;
+      writeln!(out, "title: {}", record.title)?;
+      writeln!(out, "tags: {}", record.tags.join(", "))?;
+      match &record.state {
+        StateRecord::PendingReview { approvals } => writeln!(
+          out,
+          "state: pending_review, {} of {} approvals",
+          approvals.len(),
+          record.quorum
+        )?,
+        _ => writeln!(out, "state: {}", post.state_name())?,
+      }
+      if let Some(published_at) = record.published_at {
+        writeln!(out, "published: {}", site::format_date(published_at))?;
+      }
+      writeln!(out, "\n{}\n", record.content)?;
+      for revision in &record.history {
+        let change = match &revision.change {
+          Change::Created => "created",
+          Change::Edited { .. } => "edited",
+          Change::Retitled { .. } => "retitled",
+          Change::Retagged { .. } => "retagged",
+          Change::Scheduled { .. } => "scheduled",
+          Change::ReviewRequested => "submitted",
+          Change::Approved => "approved",
+          Change::Rejected => "rejected",
+          Change::Published => "published",
+        };
+        writeln!(
+          out,
+          "{} {} {} {}",
+          revision.number, revision.timestamp, revision.author, change
+        )?;
+      }
+    }
+    Command::Export { dir } => {
+      let posts = repository
+        .ids()?
+        .iter()
+        .map(|id| load(repository, clock, id))
+        .collect::<Result<Vec<_>, _>>()?;
+      let exported = site::export(&posts, &dir)?;
+      writeln!(out, "exported {} posts to {}", exported, dir.display())?;
+    }
+    Command::Help => writeln!(out, "{}", USAGE)?,
+  }
+  Ok(())
+}
+
+fn load<R, C>(repository: &R, clock: &C, id: &str) -> Result<Post, CliError>
+where
+  R: PostRepository,
+  C: Clock + Clone + 'static,
+{
+  let mut post = repository.load(id)?;
+  post.set_clock(clock.clone());
+  Ok(post)
+}
+
+// Loads the post, applies the action and saves it, unless the action failed
+fn transition<R, C, F>(repository: &mut R, clock: &C, id: &str, f: F) -> Result<Post, CliError>
+where
+  R: PostRepository,
+  C: Clock + Clone + 'static,
+  F: FnOnce(&mut Post) -> Result<(), TransitionError>,
+{
+  let mut post = load(repository, clock, id)?;
+  f(&mut post).map_err(|error| CliError::Transition {
+    id: id.to_string(),
+    error,
+  })?;
+  repository.save(id, &post)?;
+  Ok(post)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::clock::ManualClock;
+  use crate::repository::MemoryRepository;
+
+  fn blog(
+    repository: &mut MemoryRepository,
+    clock: &ManualClock,
+    args: &str,
+  ) -> Result<String, String> {
+    let args: Vec<String> = args.split(' ').map(String::from).collect();
+    let mut out = Vec::new();
+    parse(&args)
+      .and_then(|command| run(repository, clock, command, &mut out))
+      .map_err(|e| e.to_string())?;
+    Ok(String::from_utf8(out).unwrap())
+  }
+
+  #[test]
+  fn parses_commands() {
+    let args = |args: &str| -> Vec<String> { args.split(' ').map(String::from).collect() };
+    assert_eq!(
+      Command::New {
+        id: String::from("salad"),
+        author: String::from("ann"),
+        quorum: 2,
+        title: String::from("Salad"),
+        tags: vec![String::from("food"), String::from("lunch")],
+      },
+      parse(&args(
+        "new salad --quorum 2 --author ann --title Salad --tags food,lunch,"
+      ))
+      .unwrap()
+    );
+    assert_eq!(
+      Command::List {
+        state: Some(String::from("draft"))
+      },
+      parse(&args("list --state draft")).unwrap()
+    );
+    for (args_, message) in &[
+      ("frog", "unknown command frog"),
+      ("approve salad", "approve takes ID REVIEWER"),
+      ("list soup", "list takes no arguments"),
+      ("list --state eaten", "unknown state eaten"),
+      ("show salad --state draft", "show doesn't take --state"),
+      ("new salad --quorum 0", "invalid quorum \"0\""),
+      ("schedule salad soon", "invalid timestamp \"soon\""),
+    ] {
+      assert_eq!(*message, parse(&args(args_)).unwrap_err().to_string());
+    }
+  }
+
+  #[test]
+  fn publishes_scheduled_posts_early() {
+    let mut repository = MemoryRepository::new();
+    let clock = ManualClock::at(1_700_000_000);
+    let mut blog = |args: &str| blog(&mut repository, &clock, args);
+
+    blog("new soup").unwrap();
+    assert_eq!(
+      Err(String::from("can't publish post \"soup\": it is draft")),
+      blog("publish soup")
+    );
+    blog("schedule soup 4102444800").unwrap();
+    blog("edit soup Soup").unwrap();
+    blog("submit soup").unwrap();
+    blog("approve soup alice").unwrap();
+    assert_eq!(Ok(String::from("published soup\n")), blog("publish soup"));
+    assert_eq!(Ok(String::from("Soup\n")), blog("read soup"));
+    let show = blog("show soup").unwrap();
+    assert!(show.contains("state: published\npublished: 2023-11-14\n"));
+    assert!(show.ends_with(" anonymous published\n"));
+  }
+
+  #[test]
+  fn manages_posts() {
+    let mut repository = MemoryRepository::new();
+    let clock = ManualClock::at(1_700_000_000);
+    let mut blog = |args: &str| blog(&mut repository, &clock, args);
+
+    assert_eq!(
+      Ok(String::from("created draft salad\n")),
+      blog("new salad --author ann --quorum 2 --title Salad --tags food")
+    );
+    assert_eq!(
+      Err(String::from("post \"salad\" already exists")),
+      blog("new salad")
+    );
+    blog("edit salad Salad").unwrap();
+    assert_eq!(
+      Err(String::from("can't approve post \"salad\": it is draft")),
+      blog("approve salad alice")
+    );
+    blog("submit salad").unwrap();
+    assert_eq!(
+      Err(String::from(
+        "can't edit post \"salad\": it is pending review"
+      )),
+      blog("edit salad Soup")
+    );
+    assert_eq!(
+      Ok(String::from("approved salad, 1 of 2 approvals\n")),
+      blog("approve salad alice")
+    );
+    assert_eq!(
+      Err(String::from("alice already approved post \"salad\"")),
+      blog("approve salad alice")
+    );
+    assert_eq!(
+      Err(String::from(
+        "post \"salad\" can't be read yet: it is pending review"
+      )),
+      blog("read salad")
+    );
+
+    blog("new soup").unwrap();
+    assert_eq!(
+      Err(String::from("can't reject post \"soup\": it is draft")),
+      blog("reject soup alice")
+    );
+    assert_eq!(
+      Ok(String::from("salad\tpending_review\nsoup\tdraft\n")),
+      blog("list")
+    );
+
+    assert_eq!(
+      Ok(String::from("approved and published salad\n")),
+      blog("approve salad bob")
+    );
+    assert_eq!(Ok(String::from("Salad\n")), blog("read salad"));
+    assert_eq!(
+      Ok(String::from("salad\tpublished\n")),
+      blog("list --state published")
+    );
+    assert_eq!(Err(String::from("no post \"cake\"")), blog("show cake"));
+
+    assert_eq!(
+      Ok(String::from("soup will be published on 2100-01-01\n")),
+      blog("schedule soup 4102444800")
+    );
+    blog("edit soup Soup").unwrap();
+    blog("submit soup").unwrap();
+    assert_eq!(
+      Ok(String::from("approved soup, scheduled for 2100-01-01\n")),
+      blog("approve soup alice")
+    );
+    assert_eq!(
+      Err(String::from(
+        "post \"soup\" can't be read yet: it is scheduled"
+      )),
+      blog("read soup")
+    );
+    assert_eq!(
+      Ok(String::from("soup\tscheduled\n")),
+      blog("list --state scheduled")
+    );
+    assert_eq!(
+      Err(String::from(
+        "can't publish post \"salad\": it is published"
+      )),
+      blog("publish salad")
+    );
+    clock.set(4102444800);
+    assert_eq!(
+      Ok(String::from("salad\tpublished\nsoup\tpublished\n")),
+      blog("list --state published")
+    );
+    assert_eq!(Ok(String::from("Soup\n")), blog("read soup"));
+    assert!(blog("show soup")
+      .unwrap()
+      .contains("state: published\npublished: 2100-01-01\n"));
+
+    let show = blog("show salad").unwrap();
+    assert!(show.starts_with(
+      "id: salad\nauthor: ann\ntitle: Salad\ntags: food\nstate: published\npublished: "
+    ));
+    assert!(show.contains("\n\nSalad\n\n0 "));
+    let changes: Vec<&str> = show
+      .lines()
+      .skip(9)
+      .map(|line| line.rsplit(' ').next().unwrap())
+      .collect();
+    assert_eq!(
+      vec![
+        "created",
+        "retitled",
+        "retagged",
+        "edited",
+        "submitted",
+        "approved",
+        "approved"
+      ],
+      changes
+    );
+  }
+}
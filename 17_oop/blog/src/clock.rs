@@ -0,0 +1,47 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+// Where posts get the time from, in seconds since the Unix epoch
+pub trait Clock {
+  fn now(&self) -> u64;
+}
+
+#[derive(Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+  fn now(&self) -> u64 {
+    SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map_or(0, |d| d.as_secs())
+  }
+}
+
+// A clock that only moves when told to. Clones share the same time, so a
+// test keeps one to drive the clock it gave to a post.
+#[derive(Clone, Default)]
+pub struct ManualClock {
+  now: Rc<Cell<u64>>,
+}
+
+impl ManualClock {
+  pub fn at(now: u64) -> ManualClock {
+    ManualClock {
+      now: Rc::new(Cell::new(now)),
+    }
+  }
+  pub fn set(&self, now: u64) {
+    self.now.set(now);
+  }
+  pub fn advance(&self, seconds: u64) {
+    self.now.set(self.now.get() + seconds);
+  }
+}
+
+impl Clock for ManualClock {
+  fn now(&self) -> u64 {
+    self.now.get()
+  }
+}
@@ -0,0 +1,57 @@
+// A line diff between two texts, every line of both prefixed with ' ' when
+// it is kept, '-' when removed and '+' when added
+pub fn diff_lines(old: &str, new: &str) -> String {
+  let old: Vec<&str> = old.lines().collect();
+  let new: Vec<&str> = new.lines().collect();
+
+  // common[i][j] is the length of the longest common subsequence of
+  // old[i..] and new[j..]
+  let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+  for i in (0..old.len()).rev() {
+    for j in (0..new.len()).rev() {
+      common[i][j] = if old[i] == new[j] {
+        common[i + 1][j + 1] + 1
+      } else {
+        common[i + 1][j].max(common[i][j + 1])
+      };
+    }
+  }
+
+  let mut diff = String::new();
+  let (mut i, mut j) = (0, 0);
+  while i < old.len() || j < new.len() {
+    if i < old.len() && j < new.len() && old[i] == new[j] {
+      push_line(&mut diff, ' ', old[i]);
+      i += 1;
+      j += 1;
+    } else if j == new.len() || (i < old.len() && common[i + 1][j] >= common[i][j + 1]) {
+      push_line(&mut diff, '-', old[i]);
+      i += 1;
+    } else {
+      push_line(&mut diff, '+', new[j]);
+      j += 1;
+    }
+  }
+  diff
+}
+
+fn push_line(diff: &mut String, prefix: char, line: &str) {
+  diff.push(prefix);
+  diff.push_str(line);
+  diff.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn diffs_lines() {
+    assert_eq!(
+      " salad\n-soup\n+stew\n cake\n+tea\n",
+      diff_lines("salad\nsoup\ncake", "salad\nstew\ncake\ntea\n")
+    );
+    assert_eq!("+salad\n", diff_lines("", "salad"));
+    assert_eq!("", diff_lines("", ""));
+  }
+}
@@ -1,59 +1,501 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+
+use pulldown_cmark::html;
+use pulldown_cmark::CowStr;
+use pulldown_cmark::Event;
+use pulldown_cmark::Parser;
+use pulldown_cmark::Tag;
+use serde::Deserialize;
+use serde::Serialize;
+
+pub mod cli;
+pub mod clock;
+pub mod diff;
+pub mod repository;
+pub mod site;
+
+use clock::Clock;
+use clock::SystemClock;
+use repository::StateRecord;
+
 pub struct Post {
   state: Option<Box<dyn State>>,
   content: String,
+  author: String,
+  title: String,
+  tags: Vec<String>,
+  // When it was published, in seconds since the Unix epoch
+  published_at: Option<u64>,
+  // When it should be published once approved, right away if None
+  scheduled_for: Option<u64>,
+  // How many different reviewers have to approve
+  quorum: usize,
+  history: Vec<Revision>,
+  clock: Box<dyn Clock>,
+}
+
+// What a revision did to the post
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+  Created,
+  Edited { text: String },
+  Retitled { title: String },
+  Retagged { tags: Vec<String> },
+  Scheduled { at: u64 },
+  ReviewRequested,
+  Approved,
+  Rejected,
+  Published,
+}
+
+// One change to a post, by whom and when, with the content it left
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Revision {
+  pub number: usize,
+  // Seconds since the Unix epoch
+  pub timestamp: u64,
+  pub author: String,
+  #[serde(flatten)]
+  pub change: Change,
+  pub content: String,
+}
+
+// Why an action had no effect on a post
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransitionError {
+  // The action isn't possible in the state the post is in
+  NotAllowed {
+    state: &'static str,
+    action: &'static str,
+  },
+  // The approval of this reviewer already counts
+  AlreadyApproved {
+    reviewer: String,
+  },
+}
+
+impl fmt::Display for TransitionError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      TransitionError::NotAllowed { state, action } => {
+        write!(f, "can't {} a {} post", action, state.replace('_', " "))
+      }
+      TransitionError::AlreadyApproved { reviewer } => {
+        write!(f, "{} already approved this post", reviewer)
+      }
+    }
+  }
+}
+
+impl Error for TransitionError {}
+
+impl Default for Post {
+  fn default() -> Post {
+    Post::new()
+  }
 }
 
 impl Post {
   pub fn new() -> Post {
-    Post {
+    Post::with_quorum(1)
+  }
+  pub fn with_quorum(quorum: usize) -> Post {
+    Post::written_by("anonymous", quorum)
+  }
+  // The author is who the edits and review requests are from
+  pub fn written_by(author: &str, quorum: usize) -> Post {
+    let mut post = Post {
       state: Some(Box::new(Draft {})),
       content: String::new(),
-    }
+      author: author.to_string(),
+      title: String::new(),
+      tags: Vec::new(),
+      published_at: None,
+      scheduled_for: None,
+      quorum: quorum.max(1),
+      history: Vec::new(),
+      clock: Box::new(SystemClock),
+    };
+    post.record(author, Change::Created);
+    post
+  }
+  // Where the time of revisions and of scheduled publication comes from
+  pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+    self.clock = Box::new(clock);
+  }
+  pub fn author(&self) -> &str {
+    &self.author
   }
+  // Only drafts can be edited, the text is ignored otherwise
   pub fn add_text(&mut self, text: &str) {
+    let _ = self.try_add_text(text);
+  }
+  // Fails unless the post is a draft
+  pub fn try_add_text(&mut self, text: &str) -> Result<(), TransitionError> {
+    self.editable("edit")?;
     self.content.push_str(text);
+    let author = self.author.clone();
+    self.record(
+      &author,
+      Change::Edited {
+        text: text.to_string(),
+      },
+    );
+    Ok(())
   }
   pub fn content(&self) -> &str {
     self.state.as_ref().unwrap().content(self)
   }
+  // Whether readers can see the post, scheduled posts only once their time
+  // has come
+  pub fn is_visible(&self) -> bool {
+    self.state.as_ref().unwrap().is_visible(self)
+  }
+  // The content as HTML, read as Markdown, empty until published. HTML in
+  // the content is shown as text rather than trusted, and links and images
+  // only go to web pages, mail addresses or relative paths, so a post can't
+  // add scripts to the site
+  pub fn render_html(&self) -> String {
+    let events = Parser::new(self.content()).map(|event| match event {
+      Event::Html(html) => Event::Text(html),
+      Event::Start(Tag::Link(kind, url, title)) => {
+        Event::Start(Tag::Link(kind, safe_url(url), title))
+      }
+      Event::Start(Tag::Image(kind, url, title)) => {
+        Event::Start(Tag::Image(kind, safe_url(url), title))
+      }
+      event => event,
+    });
+    let mut rendered = String::new();
+    html::push_html(&mut rendered, events);
+    rendered
+  }
+
+  pub fn title(&self) -> &str {
+    &self.title
+  }
+  // The title as it appears in URLs
+  pub fn slug(&self) -> String {
+    site::slugify(&self.title)
+  }
+  pub fn tags(&self) -> &[String] {
+    &self.tags
+  }
+  // For scheduled posts, when they will be
+  pub fn published_at(&self) -> Option<u64> {
+    self.published_at
+  }
+  pub fn scheduled_for(&self) -> Option<u64> {
+    self.scheduled_for
+  }
+  // Once approved, the post will wait for `at` instead of being published
+  // right away, only drafts can be scheduled
+  pub fn schedule(&mut self, at: u64) {
+    let _ = self.try_schedule(at);
+  }
+  // Fails unless the post is a draft, scheduling it for the same time again
+  // is fine but isn't recorded
+  pub fn try_schedule(&mut self, at: u64) -> Result<(), TransitionError> {
+    self.editable("schedule")?;
+    if self.scheduled_for != Some(at) {
+      self.scheduled_for = Some(at);
+      let author = self.author.clone();
+      self.record(&author, Change::Scheduled { at });
+    }
+    Ok(())
+  }
+  // Like the content, the title and tags only change in drafts
+  pub fn set_title(&mut self, title: &str) {
+    if self.state.as_ref().unwrap().can_edit() && self.title != title {
+      self.title = title.to_string();
+      let author = self.author.clone();
+      self.record(
+        &author,
+        Change::Retitled {
+          title: title.to_string(),
+        },
+      );
+    }
+  }
+  // Tags are kept sorted, without duplicates
+  pub fn set_tags(&mut self, tags: &[&str]) {
+    let mut tags: Vec<String> = tags.iter().map(|tag| tag.to_string()).collect();
+    tags.sort();
+    tags.dedup();
+    if self.state.as_ref().unwrap().can_edit() && self.tags != tags {
+      self.tags = tags.clone();
+      let author = self.author.clone();
+      self.record(&author, Change::Retagged { tags });
+    }
+  }
+  // draft, pending_review, scheduled or published
+  // A scheduled post counts as published once its time has come
+  pub fn state_name(&self) -> &'static str {
+    let state = self.state.as_ref().unwrap();
+    if state.is_visible(self) {
+      "published"
+    } else {
+      state.record().name()
+    }
+  }
   pub fn request_review(&mut self) {
-    // take takes ownership
-    if let Some(s) = self.state.take() {
-      self.state = Some(s.request_review())
+    let _ = self.try_request_review();
+  }
+  // Fails unless the post is a draft
+  pub fn try_request_review(&mut self) -> Result<(), TransitionError> {
+    let author = self.author.clone();
+    self
+      .transition(&author, Change::ReviewRequested, |s, _| s.request_review())
+      .then_some(())
+      .ok_or_else(|| self.not_allowed("request review of"))
+  }
+  // Publishes, or schedules, once enough different reviewers approved,
+  // approving twice counts once
+  pub fn approve(&mut self, reviewer: &str) {
+    let _ = self.try_approve(reviewer);
+  }
+  // Fails unless the post is pending review, also when the reviewer already
+  // approved it
+  pub fn try_approve(&mut self, reviewer: &str) -> Result<(), TransitionError> {
+    if self.state.as_ref().unwrap().has_approved(reviewer) {
+      return Err(TransitionError::AlreadyApproved {
+        reviewer: reviewer.to_string(),
+      });
+    }
+    self
+      .transition(reviewer, Change::Approved, |s, post| {
+        s.approve(reviewer, post)
+      })
+      .then_some(())
+      .ok_or_else(|| self.not_allowed("approve"))
+  }
+  pub fn reject(&mut self, reviewer: &str) {
+    let _ = self.try_reject(reviewer);
+  }
+  // Fails unless the post is pending review
+  pub fn try_reject(&mut self, reviewer: &str) -> Result<(), TransitionError> {
+    self
+      .transition(reviewer, Change::Rejected, |s, _| s.reject())
+      .then_some(())
+      .ok_or_else(|| self.not_allowed("reject"))
+  }
+  // Publishes a scheduled post now rather than at its time
+  pub fn publish(&mut self) {
+    let _ = self.try_publish();
+  }
+  // Fails unless the post is scheduled and its time hasn't come yet
+  pub fn try_publish(&mut self) -> Result<(), TransitionError> {
+    let author = self.author.clone();
+    self
+      .transition(&author, Change::Published, |s, post| s.publish(post))
+      .then_some(())
+      .ok_or_else(|| self.not_allowed("publish"))
+  }
+
+  // Every change that had an effect, oldest first, starting with the
+  // creation as revision 0
+  pub fn history(&self) -> &[Revision] {
+    &self.history
+  }
+  pub fn revision(&self, number: usize) -> Option<&Revision> {
+    self.history.get(number)
+  }
+  // How the content changed from one revision to another, None if either
+  // doesn't exist
+  pub fn diff(&self, from: usize, to: usize) -> Option<String> {
+    let from = self.revision(from)?;
+    let to = self.revision(to)?;
+    Some(diff::diff_lines(&from.content, &to.content))
+  }
+
+  fn not_allowed(&self, action: &'static str) -> TransitionError {
+    TransitionError::NotAllowed {
+      state: self.state_name(),
+      action,
+    }
+  }
+  fn editable(&self, action: &'static str) -> Result<(), TransitionError> {
+    if self.state.as_ref().unwrap().can_edit() {
+      Ok(())
+    } else {
+      Err(self.not_allowed(action))
     }
   }
-  pub fn approve(&mut self) {
+
+  // Only transitions that changed something are recorded, approving a draft
+  // or approving twice leaves no revision
+  fn transition<F>(&mut self, author: &str, change: Change, f: F) -> bool
+  where
+    F: FnOnce(Box<dyn State>, &Post) -> Box<dyn State>,
+  {
+    // take takes ownership
     if let Some(s) = self.state.take() {
-      self.state = Some(s.approve())
+      let before = s.record();
+      let s = f(s, self);
+      let after = s.record();
+      self.state = Some(s);
+      if after != before {
+        self.record(author, change);
+        match after {
+          StateRecord::Published => {
+            self.published_at = self.history.last().map(|revision| revision.timestamp)
+          }
+          StateRecord::Scheduled { at } => self.published_at = Some(at),
+          _ => {}
+        }
+        return true;
+      }
     }
+    false
+  }
+
+  fn record(&mut self, author: &str, change: Change) {
+    self.history.push(Revision {
+      number: self.history.len(),
+      timestamp: self.clock.now(),
+      author: author.to_string(),
+      change,
+      content: self.content.clone(),
+    });
+  }
+}
+
+// The URL a link or image goes to, `#` unless it's relative or on http,
+// https or mailto. Browsers ignore case, and whitespace and control
+// characters in the scheme, so `JavaScript:` and ` java\tscript:` are caught
+fn safe_url(url: CowStr) -> CowStr {
+  let scheme = match url.find(&[':', '/', '?', '#'][..]) {
+    Some(end) if url[end..].starts_with(':') => url[..end]
+      .chars()
+      .filter(|c| !c.is_whitespace() && !c.is_control())
+      .collect::<String>()
+      .to_ascii_lowercase(),
+    _ => return url,
+  };
+  match scheme.as_str() {
+    "http" | "https" | "mailto" => url,
+    _ => CowStr::Borrowed("#"),
   }
 }
 
 trait State {
   fn request_review(self: Box<Self>) -> Box<dyn State>;
-  fn approve(self: Box<Self>) -> Box<dyn State>;
-  fn content<'a>(&self, post: &'a Post) -> &'a str {
+  fn approve(self: Box<Self>, reviewer: &str, post: &Post) -> Box<dyn State>;
+  fn reject(self: Box<Self>) -> Box<dyn State>;
+  fn publish(self: Box<Self>, post: &Post) -> Box<dyn State>;
+  fn content<'a>(&self, _post: &'a Post) -> &'a str {
     ""
   }
+  fn is_visible(&self, _post: &Post) -> bool {
+    false
+  }
+  fn can_edit(&self) -> bool {
+    false
+  }
+  fn has_approved(&self, _reviewer: &str) -> bool {
+    false
+  }
+  // What gets saved, see repository
+  fn record(&self) -> StateRecord;
 }
 
 struct Draft {}
 impl State for Draft {
   fn request_review(self: Box<Self>) -> Box<dyn State> {
-    Box::new(PendingReview {})
+    Box::new(PendingReview {
+      approvals: HashSet::new(),
+    })
+  }
+  fn approve(self: Box<Self>, _reviewer: &str, _post: &Post) -> Box<dyn State> {
+    self
+  }
+  fn reject(self: Box<Self>) -> Box<dyn State> {
+    self
   }
-  fn approve(self: Box<Self>) -> Box<dyn State> {
+  fn publish(self: Box<Self>, _post: &Post) -> Box<dyn State> {
     self
   }
+  fn can_edit(&self) -> bool {
+    true
+  }
+  fn record(&self) -> StateRecord {
+    StateRecord::Draft
+  }
 }
 
-struct PendingReview {}
+struct PendingReview {
+  // The reviewers who approved
+  approvals: HashSet<String>,
+}
 impl State for PendingReview {
   fn request_review(self: Box<Self>) -> Box<dyn State> {
     self
   }
-  fn approve(self: Box<Self>) -> Box<dyn State> {
-    Box::new(Published {})
+  fn approve(mut self: Box<Self>, reviewer: &str, post: &Post) -> Box<dyn State> {
+    self.approvals.insert(reviewer.to_string());
+    if self.approvals.len() < post.quorum {
+      return self;
+    }
+    match post.scheduled_for {
+      Some(at) => Box::new(Scheduled { at }),
+      None => Box::new(Published {}),
+    }
+  }
+  // Back to the author for changes, the approvals are forgotten
+  fn reject(self: Box<Self>) -> Box<dyn State> {
+    Box::new(Draft {})
+  }
+  fn publish(self: Box<Self>, _post: &Post) -> Box<dyn State> {
+    self
+  }
+  fn has_approved(&self, reviewer: &str) -> bool {
+    self.approvals.contains(reviewer)
+  }
+  fn record(&self) -> StateRecord {
+    let mut approvals: Vec<String> = self.approvals.iter().cloned().collect();
+    approvals.sort();
+    StateRecord::PendingReview { approvals }
+  }
+}
+
+// Approved, waiting for its time to be published
+struct Scheduled {
+  at: u64,
+}
+
+impl State for Scheduled {
+  fn request_review(self: Box<Self>) -> Box<dyn State> {
+    self
+  }
+  fn approve(self: Box<Self>, _reviewer: &str, _post: &Post) -> Box<dyn State> {
+    self
+  }
+  fn reject(self: Box<Self>) -> Box<dyn State> {
+    self
+  }
+  // Once its time has come the post already is published
+  fn publish(self: Box<Self>, post: &Post) -> Box<dyn State> {
+    if self.is_visible(post) {
+      self
+    } else {
+      Box::new(Published {})
+    }
+  }
+  fn content<'a>(&self, post: &'a Post) -> &'a str {
+    if self.is_visible(post) {
+      &post.content
+    } else {
+      ""
+    }
+  }
+  fn is_visible(&self, post: &Post) -> bool {
+    post.clock.now() >= self.at
+  }
+  fn record(&self) -> StateRecord {
+    StateRecord::Scheduled { at: self.at }
   }
 }
 
@@ -63,10 +505,361 @@ impl State for Published {
   fn request_review(self: Box<Self>) -> Box<dyn State> {
     self
   }
-  fn approve(self: Box<Self>) -> Box<dyn State> {
+  fn approve(self: Box<Self>, _reviewer: &str, _post: &Post) -> Box<dyn State> {
+    self
+  }
+  fn reject(self: Box<Self>) -> Box<dyn State> {
+    self
+  }
+  fn publish(self: Box<Self>, _post: &Post) -> Box<dyn State> {
     self
   }
   fn content<'a>(&self, post: &'a Post) -> &'a str {
     &post.content
   }
+  fn is_visible(&self, _post: &Post) -> bool {
+    true
+  }
+  fn record(&self) -> StateRecord {
+    StateRecord::Published
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use clock::ManualClock;
+
+  fn draft() -> Post {
+    let mut post = Post::new();
+    post.add_text("I ate a salad for lunch today");
+    post
+  }
+
+  fn pending_review() -> Post {
+    let mut post = draft();
+    post.request_review();
+    post
+  }
+
+  fn published() -> Post {
+    let mut post = pending_review();
+    post.approve("alice");
+    post
+  }
+
+  #[test]
+  fn draft_transitions() {
+    let mut post = draft();
+    post.approve("alice");
+    assert_eq!("", post.content());
+    post.reject("bob");
+    assert_eq!("", post.content());
+
+    // Still a draft, so it can be edited and sent for review
+    post.add_text(" and a soup");
+    post.request_review();
+    post.approve("alice");
+    assert_eq!("I ate a salad for lunch today and a soup", post.content());
+  }
+
+  #[test]
+  fn pending_review_transitions() {
+    let mut post = pending_review();
+    post.request_review();
+    assert_eq!("", post.content());
+    post.approve("alice");
+    assert_eq!("I ate a salad for lunch today", post.content());
+
+    let mut post = pending_review();
+    post.reject("bob");
+    // Rejected posts are drafts again
+    post.approve("alice");
+    assert_eq!("", post.content());
+    post.add_text("!");
+    post.request_review();
+    post.approve("alice");
+    assert_eq!("I ate a salad for lunch today!", post.content());
+  }
+
+  #[test]
+  fn published_transitions() {
+    let mut post = published();
+    post.request_review();
+    post.reject("bob");
+    post.approve("alice");
+    assert_eq!("I ate a salad for lunch today", post.content());
+  }
+
+  #[test]
+  fn quorum_of_distinct_reviewers() {
+    let mut post = Post::with_quorum(2);
+    post.add_text("I ate a salad for lunch today");
+    post.request_review();
+
+    post.approve("alice");
+    post.approve("alice");
+    assert_eq!("", post.content());
+    post.approve("bob");
+    assert_eq!("I ate a salad for lunch today", post.content());
+  }
+
+  #[test]
+  fn rejecting_forgets_approvals() {
+    let mut post = Post::with_quorum(2);
+    post.request_review();
+    post.approve("alice");
+    post.reject("bob");
+    post.add_text("Salad");
+    post.request_review();
+
+    post.approve("bob");
+    assert_eq!("", post.content());
+    post.approve("carol");
+    assert_eq!("Salad", post.content());
+  }
+
+  #[test]
+  fn only_drafts_are_edited() {
+    let mut post = pending_review();
+    post.add_text(" and a soup");
+    post.approve("alice");
+    post.add_text(" and a cake");
+    assert_eq!("I ate a salad for lunch today", post.content());
+  }
+
+  #[test]
+  fn records_revisions() {
+    let mut post = Post::written_by("ann", 2);
+    post.add_text("Salad\n");
+    post.request_review();
+    post.approve("alice");
+    // Neither changes anything
+    post.approve("alice");
+    post.add_text("Soup\n");
+    post.reject("bob");
+    post.add_text("Soup\n");
+
+    let changes: Vec<(&str, &Change)> = post
+      .history()
+      .iter()
+      .map(|revision| (revision.author.as_str(), &revision.change))
+      .collect();
+    assert_eq!(
+      vec![
+        ("ann", &Change::Created),
+        (
+          "ann",
+          &Change::Edited {
+            text: String::from("Salad\n")
+          }
+        ),
+        ("ann", &Change::ReviewRequested),
+        ("alice", &Change::Approved),
+        ("bob", &Change::Rejected),
+        (
+          "ann",
+          &Change::Edited {
+            text: String::from("Soup\n")
+          }
+        ),
+      ],
+      changes
+    );
+    assert!(post
+      .history()
+      .windows(2)
+      .all(|w| w[0].timestamp <= w[1].timestamp));
+
+    assert_eq!(4, post.revision(4).unwrap().number);
+    assert_eq!("Salad\n", post.revision(4).unwrap().content);
+    assert_eq!(None, post.revision(6));
+    assert_eq!(Some(String::from(" Salad\n+Soup\n")), post.diff(1, 5));
+    assert_eq!(None, post.diff(1, 6));
+  }
+
+  #[test]
+  fn metadata_and_rendering() {
+    let mut post = draft();
+    post.set_title("Lunch: a salad");
+    post.set_tags(&["food", "lunch", "food"]);
+    post.add_text("\n\n* with *feta*");
+    assert_eq!("lunch-a-salad", post.slug());
+    assert_eq!(&["food", "lunch"], post.tags());
+    assert_eq!("", post.render_html());
+    assert_eq!(None, post.published_at());
+
+    post.request_review();
+    post.set_title("Dinner");
+    post.approve("alice");
+    assert_eq!("Lunch: a salad", post.title());
+    assert_eq!(
+      post.history().last().map(|r| r.timestamp),
+      post.published_at()
+    );
+    assert_eq!(
+      "<p>I ate a salad for lunch today</p>\n<ul>\n<li>with <em>feta</em></li>\n</ul>\n",
+      post.render_html()
+    );
+
+    let mut post = Post::new();
+    post.add_text("<script>alert(1)</script>\n\nSo <b>good</b>");
+    post.request_review();
+    post.approve("alice");
+    assert_eq!(
+      "&lt;script&gt;alert(1)&lt;/script&gt;\n<p>So &lt;b&gt;good&lt;/b&gt;</p>\n",
+      post.render_html()
+    );
+  }
+
+  #[test]
+  fn links_only_go_to_safe_urls() {
+    let mut post = Post::new();
+    post.add_text(
+      "[click](javascript:alert(document.cookie)) [me]( JavaScript:alert(1)) \
+       [data](data:text/html,hi) <VBScript:msgbox> \
+       [web](https://example.com) [mail](mailto:ann@example.com) [up](../soup.html#top)",
+    );
+    post.request_review();
+    post.approve("alice");
+    assert_eq!(
+      "<p><a href=\"#\">click</a> <a href=\"#\">me</a> \
+       <a href=\"#\">data</a> <a href=\"#\">VBScript:msgbox</a> \
+       <a href=\"https://example.com\">web</a> <a href=\"mailto:ann@example.com\">mail</a> \
+       <a href=\"../soup.html#top\">up</a></p>\n",
+      post.render_html()
+    );
+  }
+
+  #[test]
+  fn images_only_come_from_safe_urls() {
+    let mut post = Post::new();
+    post.add_text("![x](javascript:alert(1)) ![y](DATA:image/svg+xml,<svg>) ![soup](soup.png)");
+    post.request_review();
+    post.approve("alice");
+    assert_eq!(
+      "<p><img src=\"#\" alt=\"x\" /> <img src=\"#\" alt=\"y\" /> \
+       <img src=\"soup.png\" alt=\"soup\" /></p>\n",
+      post.render_html()
+    );
+  }
+
+  #[test]
+  fn scheduled_posts_wait_for_their_time() {
+    let clock = ManualClock::at(1000);
+    let mut post = Post::written_by("ann", 1);
+    post.set_clock(clock.clone());
+    post.add_text("Salad");
+    post.schedule(2000);
+    post.request_review();
+    // Only drafts can be rescheduled
+    post.schedule(1500);
+    post.approve("alice");
+
+    assert_eq!(Some(2000), post.published_at());
+    assert!(!post.is_visible());
+    assert_eq!("", post.content());
+    assert_eq!("scheduled", post.state_name());
+    clock.set(1999);
+    assert_eq!("", post.content());
+    clock.advance(1);
+    assert!(post.is_visible());
+    assert_eq!("published", post.state_name());
+    assert_eq!("Salad", post.content());
+    assert_eq!("<p>Salad</p>\n", post.render_html());
+
+    // Nothing sends a scheduled post back
+    post.reject("bob");
+    post.request_review();
+    assert_eq!("Salad", post.content());
+    let timestamps: Vec<u64> = post.history()[1..].iter().map(|r| r.timestamp).collect();
+    assert_eq!(vec![1000; 4], timestamps);
+  }
+
+  #[test]
+  fn scheduled_posts_can_be_published_early() {
+    let clock = ManualClock::at(1000);
+    let mut post = Post::written_by("ann", 1);
+    post.set_clock(clock.clone());
+    post.add_text("Salad");
+    assert!(post.try_publish().is_err());
+    post.schedule(2000);
+    post.request_review();
+    assert_eq!(
+      "can't publish a pending review post",
+      post.try_publish().unwrap_err().to_string()
+    );
+    post.approve("alice");
+
+    clock.set(1500);
+    assert_eq!(Ok(()), post.try_publish());
+    assert_eq!("published", post.state_name());
+    assert_eq!(Some(1500), post.published_at());
+    assert_eq!("Salad", post.content());
+    assert_eq!(
+      Some(&Change::Published),
+      post.history().last().map(|r| &r.change)
+    );
+    assert!(post.try_publish().is_err());
+
+    // Nothing to do once the time has come
+    let mut post = Post::new();
+    post.set_clock(clock.clone());
+    post.schedule(2000);
+    post.request_review();
+    post.approve("alice");
+    clock.set(2000);
+    assert_eq!(
+      "can't publish a published post",
+      post.try_publish().unwrap_err().to_string()
+    );
+    assert_eq!(Some(2000), post.published_at());
+  }
+
+  #[test]
+  fn failed_transitions_are_errors() {
+    let mut post = draft();
+    assert_eq!("draft", post.state_name());
+    let error = post.try_approve("alice").unwrap_err();
+    assert_eq!(
+      TransitionError::NotAllowed {
+        state: "draft",
+        action: "approve"
+      },
+      error
+    );
+    assert_eq!("can't approve a draft post", error.to_string());
+    assert!(post.try_reject("alice").is_err());
+
+    assert_eq!(Ok(()), post.try_request_review());
+    assert_eq!("pending_review", post.state_name());
+    assert_eq!(
+      "can't request review of a pending review post",
+      post.try_request_review().unwrap_err().to_string()
+    );
+    assert_eq!(
+      "can't edit a pending review post",
+      post.try_add_text("Soup").unwrap_err().to_string()
+    );
+    assert!(post.try_schedule(2000).is_err());
+    assert_eq!(Ok(()), post.try_reject("alice"));
+    assert_eq!(Ok(()), post.try_schedule(2000));
+    assert_eq!(Ok(()), post.try_add_text("Soup"));
+
+    let mut post = Post::with_quorum(2);
+    post.request_review();
+    assert_eq!(Ok(()), post.try_approve("alice"));
+    // The same reviewer again changes nothing
+    let error = post.try_approve("alice").unwrap_err();
+    assert_eq!(
+      TransitionError::AlreadyApproved {
+        reviewer: String::from("alice")
+      },
+      error
+    );
+    assert_eq!("alice already approved this post", error.to_string());
+    assert_eq!(Ok(()), post.try_approve("bob"));
+    assert_eq!("published", post.state_name());
+    assert!(post.try_approve("carol").is_err());
+  }
 }
@@ -1,14 +1,43 @@
-use blog::Post;
+use std::env;
+use std::process;
 
-fn main() {
-  let mut post = Post::new();
+use blog::cli;
+use blog::clock::SystemClock;
+use blog::repository::FileRepository;
 
-  post.add_text("I ate a salad for lunch today");
-  assert_eq!("", post.content());
+/*
+  cargo run -- new salad --author ann --quorum 2
+  cargo run -- edit salad "I ate a salad for lunch today"
+  cargo run -- submit salad
+  cargo run -- approve salad alice
+  cargo run -- approve salad bob
+  cargo run -- read salad
+  cargo run -- schedule soup 4102444800
+  ...
+  cargo run -- publish soup
+*/
+fn main() {
+  let args: Vec<String> = env::args().skip(1).collect();
 
-  post.request_review();
-  assert_eq!("", post.content());
+  let command = cli::parse(&args).unwrap_or_else(|err| {
+    eprintln!("blog: {}", err);
+    eprintln!("Try 'blog help' for more information.");
+    process::exit(2);
+  });
 
-  post.approve();
-  assert_eq!("I ate a salad for lunch today", post.content());
+  let dir = env::var_os("BLOG_DIR").unwrap_or_else(|| "posts".into());
+  let result = FileRepository::open(dir)
+    .map_err(cli::CliError::from)
+    .and_then(|mut repository| {
+      cli::run(
+        &mut repository,
+        &SystemClock,
+        command,
+        &mut std::io::stdout(),
+      )
+    });
+  if let Err(e) = result {
+    eprintln!("blog: {}", e);
+    process::exit(1);
+  }
 }
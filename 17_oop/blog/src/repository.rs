@@ -0,0 +1,351 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::clock::SystemClock;
+use crate::Draft;
+use crate::PendingReview;
+use crate::Post;
+use crate::Published;
+use crate::Revision;
+use crate::Scheduled;
+use crate::State;
+
+// The state of a post as it is saved
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "name", rename_all = "snake_case")]
+pub enum StateRecord {
+  Draft,
+  PendingReview { approvals: Vec<String> },
+  Scheduled { at: u64 },
+  Published,
+}
+
+impl StateRecord {
+  // How the state is written in the JSON and on the command line
+  pub fn name(&self) -> &'static str {
+    match self {
+      StateRecord::Draft => "draft",
+      StateRecord::PendingReview { .. } => "pending_review",
+      StateRecord::Scheduled { .. } => "scheduled",
+      StateRecord::Published => "published",
+    }
+  }
+
+  fn into_state(self) -> Box<dyn State> {
+    match self {
+      StateRecord::Draft => Box::new(Draft {}),
+      StateRecord::PendingReview { approvals } => Box::new(PendingReview {
+        approvals: approvals.into_iter().collect(),
+      }),
+      StateRecord::Scheduled { at } => Box::new(Scheduled { at }),
+      StateRecord::Published => Box::new(Published {}),
+    }
+  }
+}
+
+// Everything about a post, enough to get the same post back
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PostRecord {
+  pub content: String,
+  pub author: String,
+  // Missing from posts saved before posts had them
+  #[serde(default)]
+  pub title: String,
+  #[serde(default)]
+  pub tags: Vec<String>,
+  #[serde(default)]
+  pub published_at: Option<u64>,
+  #[serde(default)]
+  pub scheduled_for: Option<u64>,
+  pub quorum: usize,
+  pub state: StateRecord,
+  pub history: Vec<Revision>,
+}
+
+impl From<&Post> for PostRecord {
+  fn from(post: &Post) -> PostRecord {
+    PostRecord {
+      content: post.content.clone(),
+      author: post.author.clone(),
+      title: post.title.clone(),
+      tags: post.tags.clone(),
+      published_at: post.published_at,
+      scheduled_for: post.scheduled_for,
+      quorum: post.quorum,
+      state: post.state.as_ref().unwrap().record(),
+      history: post.history.clone(),
+    }
+  }
+}
+
+impl From<PostRecord> for Post {
+  fn from(record: PostRecord) -> Post {
+    Post {
+      state: Some(record.state.into_state()),
+      content: record.content,
+      author: record.author,
+      title: record.title,
+      tags: record.tags,
+      published_at: record.published_at,
+      scheduled_for: record.scheduled_for,
+      quorum: record.quorum.max(1),
+      history: record.history,
+      // Clocks aren't saved, set_clock sets another one
+      clock: Box::new(SystemClock),
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum RepositoryError {
+  NotFound(String),
+  // Ids name files, so they can't be paths
+  InvalidId(String),
+  Io(io::Error),
+  Json(serde_json::Error),
+}
+
+impl fmt::Display for RepositoryError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      RepositoryError::NotFound(id) => write!(f, "no post {:?}", id),
+      RepositoryError::InvalidId(id) => write!(f, "invalid post id {:?}", id),
+      RepositoryError::Io(e) => write!(f, "{}", e),
+      RepositoryError::Json(e) => write!(f, "invalid post: {}", e),
+    }
+  }
+}
+
+impl Error for RepositoryError {}
+
+impl From<io::Error> for RepositoryError {
+  fn from(e: io::Error) -> RepositoryError {
+    RepositoryError::Io(e)
+  }
+}
+
+impl From<serde_json::Error> for RepositoryError {
+  fn from(e: serde_json::Error) -> RepositoryError {
+    RepositoryError::Json(e)
+  }
+}
+
+// Where posts are kept between runs, by id
+pub trait PostRepository {
+  // Replaces the post with that id if there is one
+  fn save(&mut self, id: &str, post: &Post) -> Result<(), RepositoryError>;
+  fn load(&self, id: &str) -> Result<Post, RepositoryError>;
+  // Sorted
+  fn ids(&self) -> Result<Vec<String>, RepositoryError>;
+}
+
+// Keeps the JSON of the posts in memory
+#[derive(Default)]
+pub struct MemoryRepository {
+  posts: BTreeMap<String, String>,
+}
+
+impl MemoryRepository {
+  pub fn new() -> MemoryRepository {
+    MemoryRepository::default()
+  }
+}
+
+impl PostRepository for MemoryRepository {
+  fn save(&mut self, id: &str, post: &Post) -> Result<(), RepositoryError> {
+    let json = serde_json::to_string(&PostRecord::from(post))?;
+    self.posts.insert(id.to_string(), json);
+    Ok(())
+  }
+  fn load(&self, id: &str) -> Result<Post, RepositoryError> {
+    let json = self
+      .posts
+      .get(id)
+      .ok_or_else(|| RepositoryError::NotFound(id.to_string()))?;
+    let record: PostRecord = serde_json::from_str(json)?;
+    Ok(Post::from(record))
+  }
+  fn ids(&self) -> Result<Vec<String>, RepositoryError> {
+    Ok(self.posts.keys().cloned().collect())
+  }
+}
+
+// Keeps each post in `<id>.json` in a directory
+pub struct FileRepository {
+  dir: PathBuf,
+}
+
+impl FileRepository {
+  // Creates the directory if needed
+  pub fn open<P: Into<PathBuf>>(dir: P) -> Result<FileRepository, RepositoryError> {
+    let dir = dir.into();
+    fs::create_dir_all(&dir)?;
+    Ok(FileRepository { dir })
+  }
+
+  fn path(&self, id: &str) -> Result<PathBuf, RepositoryError> {
+    let valid = !id.is_empty()
+      && !id.starts_with('.')
+      && id
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.');
+    if !valid {
+      return Err(RepositoryError::InvalidId(id.to_string()));
+    }
+    Ok(self.dir.join(format!("{}.json", id)))
+  }
+}
+
+impl PostRepository for FileRepository {
+  fn save(&mut self, id: &str, post: &Post) -> Result<(), RepositoryError> {
+    let path = self.path(id)?;
+    let json = serde_json::to_string_pretty(&PostRecord::from(post))?;
+    // Written next to it then renamed, so a post is never half saved
+    let temp = self.dir.join(format!(".{}.json.tmp", id));
+    fs::write(&temp, json)?;
+    fs::rename(&temp, path)?;
+    Ok(())
+  }
+  fn load(&self, id: &str) -> Result<Post, RepositoryError> {
+    let json = match fs::read_to_string(self.path(id)?) {
+      Err(e) if e.kind() == io::ErrorKind::NotFound => {
+        return Err(RepositoryError::NotFound(id.to_string()))
+      }
+      json => json?,
+    };
+    let record: PostRecord = serde_json::from_str(&json)?;
+    Ok(Post::from(record))
+  }
+  fn ids(&self) -> Result<Vec<String>, RepositoryError> {
+    let mut ids = Vec::new();
+    for entry in fs::read_dir(&self.dir)? {
+      let name = entry?.file_name();
+      let name = name.to_string_lossy();
+      if let Some(id) = name.strip_suffix(".json") {
+        if !id.starts_with('.') {
+          ids.push(id.to_string());
+        }
+      }
+    }
+    ids.sort();
+    Ok(ids)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // A post in each state, the pending one halfway through its quorum and
+  // the scheduled one never visible
+  fn posts() -> Vec<Post> {
+    let mut draft = Post::with_quorum(2);
+    draft.add_text("Salad");
+
+    let mut pending = Post::with_quorum(2);
+    pending.add_text("Soup");
+    pending.request_review();
+    pending.approve("alice");
+
+    let mut published = Post::new();
+    published.set_title("Cake");
+    published.set_tags(&["dessert"]);
+    published.add_text("Cake");
+    published.request_review();
+    published.approve("bob");
+
+    let mut scheduled = Post::new();
+    scheduled.add_text("Tea");
+    scheduled.schedule(u64::MAX);
+    scheduled.request_review();
+    scheduled.approve("bob");
+
+    vec![draft, pending, published, scheduled]
+  }
+
+  fn round_trips<R: PostRepository>(repository: &mut R) {
+    for (i, post) in posts().iter().enumerate() {
+      repository.save(&format!("post-{}", i), post).unwrap();
+    }
+    assert_eq!(
+      vec!["post-0", "post-1", "post-2", "post-3"],
+      repository.ids().unwrap()
+    );
+
+    for (i, post) in posts().iter().enumerate() {
+      let loaded = repository.load(&format!("post-{}", i)).unwrap();
+      assert_eq!(PostRecord::from(post), PostRecord::from(&loaded));
+      assert_eq!(post.content(), loaded.content());
+    }
+
+    // The pending post remembers alice, so only a new reviewer publishes it
+    let mut pending = repository.load("post-1").unwrap();
+    pending.approve("alice");
+    assert_eq!("", pending.content());
+    pending.approve("carol");
+    assert_eq!("Soup", pending.content());
+    repository.save("post-1", &pending).unwrap();
+    assert_eq!("Soup", repository.load("post-1").unwrap().content());
+
+    assert!(matches!(
+      repository.load("post-4"),
+      Err(RepositoryError::NotFound(_))
+    ));
+  }
+
+  #[test]
+  fn memory_round_trips() {
+    round_trips(&mut MemoryRepository::new());
+  }
+
+  #[test]
+  fn files_round_trip() {
+    let dir = std::env::temp_dir().join(format!("blog_repository_{}", std::process::id()));
+    let mut repository = FileRepository::open(&dir).unwrap();
+    round_trips(&mut repository);
+
+    // Another repository on the same directory sees the same posts
+    let reopened = FileRepository::open(&dir).unwrap();
+    assert_eq!("Soup", reopened.load("post-1").unwrap().content());
+    assert!(matches!(
+      reopened.load("../post-1"),
+      Err(RepositoryError::InvalidId(_))
+    ));
+
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn saves_readable_json() {
+    let mut record = PostRecord::from(&posts()[1]);
+    for revision in &mut record.history {
+      revision.timestamp = 0;
+    }
+    assert_eq!(
+      serde_json::json!({
+        "content": "Soup",
+        "author": "anonymous",
+        "title": "",
+        "tags": [],
+        "published_at": null,
+        "scheduled_for": null,
+        "quorum": 2,
+        "state": { "name": "pending_review", "approvals": ["alice"] },
+        "history": [
+          { "number": 0, "timestamp": 0, "author": "anonymous", "change": "created", "content": "" },
+          { "number": 1, "timestamp": 0, "author": "anonymous", "change": "edited", "text": "Soup", "content": "Soup" },
+          { "number": 2, "timestamp": 0, "author": "anonymous", "change": "review_requested", "content": "Soup" },
+          { "number": 3, "timestamp": 0, "author": "alice", "change": "approved", "content": "Soup" },
+        ],
+      }),
+      serde_json::to_value(record).unwrap()
+    );
+  }
+}
@@ -0,0 +1,256 @@
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::ptr;
+
+use crate::Post;
+
+// The title as a URL path segment, lowercase words joined with dashes
+pub fn slugify(title: &str) -> String {
+  let words: Vec<String> = title
+    .split(|c: char| !c.is_alphanumeric())
+    .filter(|word| !word.is_empty())
+    .map(|word| word.to_lowercase())
+    .collect();
+  if words.is_empty() {
+    String::from("untitled")
+  } else {
+    words.join("-")
+  }
+}
+
+// Seconds since the Unix epoch as a YYYY-MM-DD date, in UTC
+pub fn format_date(timestamp: u64) -> String {
+  // Howard Hinnant's days_from_civil, backwards
+  let days = (timestamp / 86400) as i64 + 719_468;
+  let era = days.div_euclid(146_097);
+  let day_of_era = days.rem_euclid(146_097);
+  let year_of_era =
+    (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let shifted_month = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+  let month = if shifted_month < 10 {
+    shifted_month + 3
+  } else {
+    shifted_month - 9
+  };
+  let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+  format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn escape(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&#39;"),
+      c => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+fn page(title: &str, body: &str) -> String {
+  format!(
+    "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n{}</body>\n</html>\n",
+    escape(title),
+    body
+  )
+}
+
+// A published post with the file it goes in
+struct Entry<'a> {
+  post: &'a Post,
+  file: String,
+}
+
+// Links to the posts, from a page `depth` directories below the site root
+fn post_list(entries: &[&Entry], depth: usize) -> String {
+  let root = "../".repeat(depth);
+  let mut list = String::from("<ul>\n");
+  for entry in entries {
+    list.push_str(&format!(
+      "<li><a href=\"{}{}\">{}</a> {}</li>\n",
+      root,
+      entry.file,
+      escape(entry.post.title()),
+      entry
+        .post
+        .published_at()
+        .map(format_date)
+        .unwrap_or_default()
+    ));
+  }
+  list.push_str("</ul>\n");
+  list
+}
+
+// Writes the visible posts to `dir` as a static site: index.html listing
+// them newest first, a page per post named after its slug, and a page per
+// tag in tags/. Returns how many posts were exported.
+pub fn export(posts: &[Post], dir: &Path) -> io::Result<usize> {
+  let mut published: Vec<&Post> = posts.iter().filter(|post| post.is_visible()).collect();
+  published.sort_by(|a, b| {
+    b.published_at()
+      .cmp(&a.published_at())
+      .then(a.title().cmp(b.title()))
+  });
+
+  // Posts with the same slug get a number so they don't overwrite each other,
+  // nor the index
+  let mut files = HashSet::new();
+  files.insert(String::from("index.html"));
+  let entries: Vec<Entry> = published
+    .into_iter()
+    .map(|post| {
+      let slug = post.slug();
+      let mut file = format!("{}.html", slug);
+      let mut n = 2;
+      while !files.insert(file.clone()) {
+        file = format!("{}-{}.html", slug, n);
+        n += 1;
+      }
+      Entry { post, file }
+    })
+    .collect();
+
+  // Tags with the same slug, such as Food and food, share a page named after
+  // the first one found
+  let mut tags: BTreeMap<String, (&str, Vec<&Entry>)> = BTreeMap::new();
+  for entry in &entries {
+    for tag in entry.post.tags() {
+      let (_, tagged) = tags.entry(slugify(tag)).or_insert((tag, Vec::new()));
+      if !tagged.iter().any(|tagged| ptr::eq(*tagged, entry)) {
+        tagged.push(entry);
+      }
+    }
+  }
+
+  fs::create_dir_all(dir.join("tags"))?;
+  for entry in &entries {
+    let post = entry.post;
+    let mut body = format!(
+      "<p><a href=\"index.html\">Index</a></p>\n<h1>{}</h1>\n<p>{} by {}</p>\n",
+      escape(post.title()),
+      post.published_at().map(format_date).unwrap_or_default(),
+      escape(post.author())
+    );
+    if !post.tags().is_empty() {
+      let links: Vec<String> = post
+        .tags()
+        .iter()
+        .map(|tag| format!("<a href=\"tags/{}.html\">{}</a>", slugify(tag), escape(tag)))
+        .collect();
+      body.push_str(&format!("<p>Tags: {}</p>\n", links.join(", ")));
+    }
+    body.push_str(&post.render_html());
+    fs::write(dir.join(&entry.file), page(post.title(), &body))?;
+  }
+
+  let all: Vec<&Entry> = entries.iter().collect();
+  let mut body = format!("<h1>Posts</h1>\n{}", post_list(&all, 0));
+  if !tags.is_empty() {
+    body.push_str("<h2>Tags</h2>\n<ul>\n");
+    for (slug, (tag, _)) in &tags {
+      body.push_str(&format!(
+        "<li><a href=\"tags/{}.html\">{}</a></li>\n",
+        slug,
+        escape(tag)
+      ));
+    }
+    body.push_str("</ul>\n");
+  }
+  fs::write(dir.join("index.html"), page("Posts", &body))?;
+
+  for (slug, (tag, entries)) in &tags {
+    let body = format!(
+      "<p><a href=\"../index.html\">Index</a></p>\n<h1>Posts tagged {}</h1>\n{}",
+      escape(tag),
+      post_list(entries, 1)
+    );
+    fs::write(
+      dir.join("tags").join(format!("{}.html", slug)),
+      page(tag, &body),
+    )?;
+  }
+  Ok(entries.len())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn published(title: &str, tags: &[&str], content: &str) -> Post {
+    let mut post = Post::written_by("ann", 1);
+    post.set_title(title);
+    post.set_tags(tags);
+    post.add_text(content);
+    post.request_review();
+    post.approve("alice");
+    post
+  }
+
+  #[test]
+  fn slugifies_titles() {
+    assert_eq!("i-ate-a-salad", slugify("I ate a salad!"));
+    assert_eq!("crème-brûlée-2", slugify("  Crème brûlée #2 "));
+    assert_eq!("untitled", slugify("?!"));
+  }
+
+  #[test]
+  fn formats_dates() {
+    assert_eq!("1970-01-01", format_date(0));
+    assert_eq!("2000-02-29", format_date(951_782_400));
+    assert_eq!("2024-12-31", format_date(1_735_689_599));
+  }
+
+  #[test]
+  fn exports_published_posts() {
+    let dir = std::env::temp_dir().join(format!("blog_site_{}", std::process::id()));
+    let mut draft = Post::new();
+    draft.set_title("Draft");
+    let posts = vec![
+      published(
+        "Salad",
+        &["food", "green things"],
+        "# Salad\n\nIt was *good*.",
+      ),
+      published("Salad", &["Food", "food", "green-things"], "Again"),
+      published("Tea & cake", &[], "Yes"),
+      published("Index", &[], "Not the index"),
+      draft,
+    ];
+    assert_eq!(4, export(&posts, &dir).unwrap());
+
+    let read = |file: &str| fs::read_to_string(dir.join(file)).unwrap();
+    let index = read("index.html");
+    assert!(index.contains("<a href=\"salad.html\">Salad</a>"));
+    assert!(index.contains("<a href=\"salad-2.html\">Salad</a>"));
+    assert!(index.contains("<a href=\"tea-cake.html\">Tea &amp; cake</a>"));
+    assert!(index.contains("<a href=\"tags/green-things.html\">green things</a>"));
+    assert!(index.contains("<a href=\"index-2.html\">Index</a>"));
+    assert!(!index.contains("Draft"));
+    assert!(read("index-2.html").contains("Not the index"));
+
+    let salad = read("salad.html");
+    assert!(salad.contains("<h1>Salad</h1>\n<p>It was <em>good</em>.</p>\n"));
+    assert!(salad.contains("<a href=\"tags/food.html\">food</a>"));
+
+    let food = read("tags/food.html");
+    assert!(food.contains("<a href=\"../salad.html\">Salad</a>"));
+    assert!(food.contains("<a href=\"../salad-2.html\">Salad</a>"));
+    assert!(!food.contains("tea-cake"));
+    // One page per slug, listing each post once
+    assert_eq!(1, index.matches("tags/food.html").count());
+    assert_eq!(1, food.matches("salad-2.html").count());
+    assert!(read("tags/green-things.html").contains("salad-2.html"));
+
+    fs::remove_dir_all(dir).unwrap();
+  }
+}
@@ -1,15 +1,26 @@
+use std::collections::HashSet;
+
 pub struct Post {
   content: String,
 }
 
 pub struct DraftPost {
   content: String,
+  // How many different reviewers have to approve
+  quorum: usize,
 }
 
 impl Post {
+  // Posts start as drafts, which only become a Post once approved
+  #[allow(clippy::new_ret_no_self)]
   pub fn new() -> DraftPost {
+    Post::with_quorum(1)
+  }
+  #[allow(clippy::new_ret_no_self)]
+  pub fn with_quorum(quorum: usize) -> DraftPost {
     DraftPost {
       content: String::new(),
+      quorum: quorum.max(1),
     }
   }
 
@@ -25,18 +36,110 @@ impl DraftPost {
   pub fn request_review(self) -> PendingReviewPost {
     PendingReviewPost {
       content: self.content,
+      quorum: self.quorum,
+      approvals: HashSet::new(),
     }
   }
 }
 
 pub struct PendingReviewPost {
   content: String,
+  quorum: usize,
+  // The reviewers who approved
+  approvals: HashSet<String>,
+}
+
+// What approving a post gives back, it stays pending until enough
+// different reviewers approved
+pub enum Approval {
+  Pending(PendingReviewPost),
+  Published(Post),
 }
 
 impl PendingReviewPost {
-  pub fn approve(self) -> Post {
-    Post {
+  // Approving twice counts once
+  pub fn approve(mut self, reviewer: &str) -> Approval {
+    self.approvals.insert(reviewer.to_string());
+    if self.approvals.len() >= self.quorum {
+      Approval::Published(Post {
+        content: self.content,
+      })
+    } else {
+      Approval::Pending(self)
+    }
+  }
+  pub fn approvals(&self) -> usize {
+    self.approvals.len()
+  }
+  // Back to the author for changes, the approvals are forgotten
+  pub fn reject(self) -> DraftPost {
+    DraftPost {
       content: self.content,
+      quorum: self.quorum,
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn published(approval: Approval) -> Post {
+    match approval {
+      Approval::Published(post) => post,
+      Approval::Pending(_) => panic!("still pending"),
+    }
+  }
+
+  fn pending(approval: Approval) -> PendingReviewPost {
+    match approval {
+      Approval::Pending(post) => post,
+      Approval::Published(_) => panic!("already published"),
+    }
+  }
+
+  #[test]
+  fn draft_to_pending_review() {
+    let mut post = Post::new();
+    post.add_text("I ate a salad for lunch today");
+    let post = post.request_review();
+    assert_eq!("I ate a salad for lunch today", post.content);
+  }
+
+  #[test]
+  fn pending_review_to_published() {
+    let mut post = Post::new();
+    post.add_text("I ate a salad for lunch today");
+    let post = published(post.request_review().approve("alice"));
+    assert_eq!("I ate a salad for lunch today", post.content());
+  }
+
+  #[test]
+  fn pending_review_back_to_draft() {
+    let mut post = Post::new();
+    post.add_text("I ate a salad for lunch today");
+
+    // Only a DraftPost has add_text, so the rejected post can be edited again
+    let mut post = post.request_review().reject();
+    post.add_text(", and a soup");
+    let post = published(post.request_review().approve("alice"));
+    assert_eq!("I ate a salad for lunch today, and a soup", post.content());
+  }
+
+  #[test]
+  fn quorum_of_distinct_reviewers() {
+    let mut post = Post::with_quorum(2);
+    post.add_text("I ate a salad for lunch today");
+
+    let post = pending(post.request_review().approve("alice"));
+    let post = pending(post.approve("alice"));
+    assert_eq!(1, post.approvals());
+    let post = published(post.approve("bob"));
+    assert_eq!("I ate a salad for lunch today", post.content());
+
+    // Rejecting starts the count over
+    let post = pending(Post::with_quorum(2).request_review().approve("alice"));
+    let post = pending(post.reject().request_review().approve("bob"));
+    assert_eq!(1, post.approvals());
+  }
+}
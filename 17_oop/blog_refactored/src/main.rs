@@ -1,12 +1,27 @@
+use blog_refactored::Approval;
 use blog_refactored::Post;
 
 fn main() {
-  let mut post = Post::new();
+  // Two different reviewers have to approve
+  let mut post = Post::with_quorum(2);
 
   post.add_text("I ate a salad for lunch today");
 
   let post = post.request_review();
 
-  let post = post.approve();
-  assert_eq!("I ate a salad for lunch today", post.content());
+  // The reviewer sends it back, the author edits it again
+  let mut post = post.reject();
+  post.add_text(", and a soup");
+  let mut post = post.request_review();
+
+  for reviewer in &["alice", "alice", "bob"] {
+    post = match post.approve(reviewer) {
+      Approval::Pending(post) => post,
+      Approval::Published(post) => {
+        assert_eq!("I ate a salad for lunch today, and a soup", post.content());
+        return;
+      }
+    };
+  }
+  unreachable!("bob's approval publishes the post");
 }
@@ -0,0 +1,136 @@
+use std::error::Error;
+use std::fmt;
+
+// An edge of the diagram, `action` takes a machine from `from` to `to`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transition {
+  pub from: &'static str,
+  pub action: &'static str,
+  pub to: &'static str,
+}
+
+// An action that isn't a transition of the state the machine is in
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransitionError {
+  pub state: &'static str,
+  pub action: &'static str,
+}
+
+impl fmt::Display for TransitionError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "can't {} in state {}", self.action, self.state)
+  }
+}
+
+impl Error for TransitionError {}
+
+// Implemented by #[derive(StateMachine)] on the enum listing the states
+pub trait StateMachine {
+  // The name of the enum
+  const NAME: &'static str;
+  const STATES: &'static [&'static str];
+  const INITIAL: &'static str;
+  const TRANSITIONS: &'static [Transition];
+
+  fn name(&self) -> &'static str;
+
+  // Where `action` takes a machine in `state`, if anywhere
+  fn next(state: &str, action: &str) -> Option<&'static str> {
+    Self::TRANSITIONS
+      .iter()
+      .find(|t| t.from == state && t.action == action)
+      .map(|t| t.to)
+  }
+
+  // The transition diagram in Graphviz's DOT language, `dot -Tsvg` draws it.
+  // Every ID is quoted, so states named like the keywords of the language,
+  // Node or Graph, are fine.
+  fn dot() -> String {
+    let mut dot = format!("digraph \"{}\" {{\n", Self::NAME);
+    dot.push_str(&format!("  \"{}\" [shape=point];\n", START));
+    for state in Self::STATES {
+      dot.push_str(&format!("  \"{}\";\n", state));
+    }
+    dot.push_str(&format!("  \"{}\" -> \"{}\";\n", START, Self::INITIAL));
+    for t in Self::TRANSITIONS {
+      dot.push_str(&format!(
+        "  \"{}\" -> \"{}\" [label=\"{}\"];\n",
+        t.from, t.to, t.action
+      ));
+    }
+    dot.push_str("}\n");
+    dot
+  }
+}
+
+// The point the arrow to the initial state comes from, the parentheses keep
+// it apart from the states, whose names are identifiers
+const START: &str = "(start)";
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // States named after DOT keywords, and one named like the start point
+  enum Graph {
+    Node,
+    Start,
+  }
+
+  impl StateMachine for Graph {
+    const NAME: &'static str = "Graph";
+    const STATES: &'static [&'static str] = &["Node", "start"];
+    const INITIAL: &'static str = "Node";
+    const TRANSITIONS: &'static [Transition] = &[
+      Transition {
+        from: "Node",
+        action: "edge",
+        to: "start",
+      },
+      Transition {
+        from: "start",
+        action: "subgraph",
+        to: "Node",
+      },
+    ];
+
+    fn name(&self) -> &'static str {
+      match self {
+        Graph::Node => "Node",
+        Graph::Start => "start",
+      }
+    }
+  }
+
+  #[test]
+  fn follows_the_transitions() {
+    assert_eq!("start", Graph::Start.name());
+    assert_eq!(Some("start"), Graph::next(Graph::Node.name(), "edge"));
+    assert_eq!(None, Graph::next("start", "edge"));
+    assert_eq!(
+      "can't edge in state start",
+      TransitionError {
+        state: "start",
+        action: "edge"
+      }
+      .to_string()
+    );
+  }
+
+  #[test]
+  fn quotes_dot_ids() {
+    assert_eq!(
+      "\
+digraph \"Graph\" {
+  \"(start)\" [shape=point];
+  \"Node\";
+  \"start\";
+  \"(start)\" -> \"Node\";
+  \"Node\" -> \"start\" [label=\"edge\"];
+  \"start\" -> \"Node\" [label=\"subgraph\"];
+}
+",
+      Graph::dot()
+    );
+  }
+}
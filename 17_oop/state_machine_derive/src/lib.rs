@@ -0,0 +1,462 @@
+// Generates the state pattern of chapter 17 from an enum listing the states:
+//
+//   #[derive(StateMachine)]
+//   #[state_machine(typestate, data = "String")]
+//   enum Post {
+//     #[initial]
+//     #[transition(request_review -> PendingReview)]
+//     Draft,
+//     #[transition(approve -> Published, reject -> Draft)]
+//     PendingReview,
+//     Published,
+//   }
+//
+// implements state_machine::StateMachine for the enum, and adds a module
+// named after it, post_machine here, with either
+//  - boxed, the default: a State trait with a method per action, a unit
+//    struct per state implementing it, and a Machine holding a
+//    Box<dyn State> and the data, if there is any, like blog's Post
+//  - typestate: a struct per state carrying the data, with a method per
+//    action of that state consuming it, like blog_refactored's posts
+// The enum has to be declared at the root of a module, the generated module
+// refers to it through super.
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::parse::ParseStream;
+use syn::punctuated::Punctuated;
+use syn::Ident;
+use syn::Token;
+
+// Names the generated code already uses, for actions and for states
+const RESERVED: &[&str] = &["new", "state", "data"];
+const RESERVED_STATES: &[&str] = &["State", "Machine"];
+
+enum Style {
+  Boxed { data: Option<syn::Type> },
+  TypeState { data: syn::Type },
+}
+
+struct Edge {
+  from: Ident,
+  action: Ident,
+  to: Ident,
+}
+
+// `action -> Target`
+fn parse_edge(input: ParseStream) -> syn::Result<(Ident, Ident)> {
+  let action: Ident = input.parse()?;
+  input.parse::<Token![->]>()?;
+  let to: Ident = input.parse()?;
+  Ok((action, to))
+}
+
+fn parse_style(ast: &syn::DeriveInput) -> syn::Result<Style> {
+  let mut style = Style::Boxed { data: None };
+  for attr in ast
+    .attrs
+    .iter()
+    .filter(|attr| attr.path.is_ident("state_machine"))
+  {
+    let list = match attr.parse_meta()? {
+      syn::Meta::List(list) => list,
+      meta => {
+        return Err(syn::Error::new_spanned(
+          meta,
+          "expected #[state_machine(...)]",
+        ))
+      }
+    };
+    let mut typestate = false;
+    let mut data = None;
+    for nested in list.nested {
+      match nested {
+        syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("boxed") => typestate = false,
+        syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("typestate") => {
+          typestate = true
+        }
+        syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+          path,
+          lit: syn::Lit::Str(lit),
+          ..
+        }))
+          if path.is_ident("data") =>
+        {
+          data = Some(lit.parse()?)
+        }
+        nested => {
+          return Err(syn::Error::new_spanned(
+            nested,
+            "expected boxed, typestate or data = \"Type\"",
+          ))
+        }
+      }
+    }
+    style = if typestate {
+      Style::TypeState {
+        data: data.unwrap_or_else(|| syn::parse_quote!(())),
+      }
+    } else {
+      Style::Boxed { data }
+    };
+  }
+  Ok(style)
+}
+
+// The states, the initial one and the transitions, checked
+fn parse_states(ast: &syn::DeriveInput) -> syn::Result<(Vec<Ident>, Ident, Vec<Edge>)> {
+  let variants = match &ast.data {
+    syn::Data::Enum(data) => &data.variants,
+    _ => {
+      return Err(syn::Error::new_spanned(
+        &ast.ident,
+        "StateMachine can only be derived for enums",
+      ))
+    }
+  };
+  if variants.is_empty() {
+    return Err(syn::Error::new_spanned(
+      &ast.ident,
+      "a state machine needs states",
+    ));
+  }
+
+  let states: Vec<Ident> = variants
+    .iter()
+    .map(|variant| variant.ident.clone())
+    .collect();
+  let mut initial = None;
+  let mut edges: Vec<Edge> = Vec::new();
+  for variant in variants {
+    if !matches!(variant.fields, syn::Fields::Unit) {
+      return Err(syn::Error::new_spanned(variant, "states can't have fields"));
+    }
+    if RESERVED_STATES.contains(&variant.ident.to_string().as_str()) {
+      return Err(syn::Error::new_spanned(
+        &variant.ident,
+        "this name is used by the generated code",
+      ));
+    }
+    // The struct of the state would hide the enum in the generated module
+    if variant.ident == ast.ident {
+      return Err(syn::Error::new_spanned(
+        &variant.ident,
+        "a state can't have the name of the enum",
+      ));
+    }
+    for attr in &variant.attrs {
+      if attr.path.is_ident("initial") {
+        if !attr.tokens.is_empty() {
+          return Err(syn::Error::new_spanned(
+            &attr.tokens,
+            "#[initial] takes no arguments",
+          ));
+        }
+        if initial.is_some() {
+          return Err(syn::Error::new_spanned(
+            attr,
+            "only one state can be #[initial]",
+          ));
+        }
+        initial = Some(variant.ident.clone());
+      } else if attr.path.is_ident("transition") {
+        let parsed = attr.parse_args_with(|input: ParseStream| {
+          Punctuated::<(Ident, Ident), Token![,]>::parse_terminated_with(input, parse_edge)
+        })?;
+        for (action, to) in parsed {
+          if RESERVED.contains(&action.to_string().as_str()) {
+            return Err(syn::Error::new_spanned(
+              &action,
+              "this name is used by the generated code",
+            ));
+          }
+          if !states.contains(&to) {
+            return Err(syn::Error::new_spanned(&to, "unknown state"));
+          }
+          if edges
+            .iter()
+            .any(|edge| edge.from == variant.ident && edge.action == action)
+          {
+            return Err(syn::Error::new_spanned(
+              &action,
+              "this state already has this action",
+            ));
+          }
+          edges.push(Edge {
+            from: variant.ident.clone(),
+            action,
+            to,
+          });
+        }
+      }
+    }
+  }
+  // The first state unless told otherwise
+  let initial = initial.unwrap_or_else(|| states[0].clone());
+  Ok((states, initial, edges))
+}
+
+// A run of capitals is one word, HTTPState becomes http_state
+fn snake_case(name: &str) -> String {
+  let chars: Vec<char> = name.chars().collect();
+  let mut snake = String::new();
+  for (i, &c) in chars.iter().enumerate() {
+    if c.is_uppercase() && i > 0 {
+      let previous = chars[i - 1];
+      let starts_word = matches!(chars.get(i + 1), Some(next) if next.is_lowercase());
+      if previous.is_lowercase()
+        || previous.is_ascii_digit()
+        || (previous.is_uppercase() && starts_word)
+      {
+        snake.push('_');
+      }
+    }
+    snake.extend(c.to_lowercase());
+  }
+  snake
+}
+
+fn impl_state_machine(ast: &syn::DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+  let name = &ast.ident;
+  let vis = &ast.vis;
+  let style = parse_style(ast)?;
+  let (states, initial, edges) = parse_states(ast)?;
+
+  let state_names: Vec<String> = states.iter().map(|state| state.to_string()).collect();
+  let initial_name = initial.to_string();
+  let from_names = edges.iter().map(|edge| edge.from.to_string());
+  let action_names = edges.iter().map(|edge| edge.action.to_string());
+  let to_names = edges.iter().map(|edge| edge.to.to_string());
+  let name_string = name.to_string();
+  let machine = quote! {
+    impl ::state_machine::StateMachine for #name {
+      const NAME: &'static str = #name_string;
+      const STATES: &'static [&'static str] = &[#(#state_names),*];
+      const INITIAL: &'static str = #initial_name;
+      const TRANSITIONS: &'static [::state_machine::Transition] = &[
+        #(::state_machine::Transition { from: #from_names, action: #action_names, to: #to_names }),*
+      ];
+
+      fn name(&self) -> &'static str {
+        match self {
+          #(#name::#states => #state_names),*
+        }
+      }
+    }
+  };
+
+  let module = Ident::new(
+    &format!("{}_machine", snake_case(&name.to_string())),
+    Span::call_site(),
+  );
+  let generated = match style {
+    Style::Boxed { data } => boxed(name, &states, &initial, &edges, data.as_ref()),
+    Style::TypeState { data } => typestate(name, &states, &initial, &edges, &data),
+  };
+  Ok(quote! {
+    #machine
+
+    // Not every workflow uses every action
+    #[allow(dead_code)]
+    #vis mod #module {
+      use super::*;
+
+      #generated
+    }
+  })
+}
+
+fn boxed(
+  name: &Ident,
+  states: &[Ident],
+  initial: &Ident,
+  edges: &[Edge],
+  data: Option<&syn::Type>,
+) -> proc_macro2::TokenStream {
+  // Every action, in the order they first appear
+  let mut actions: Vec<&Ident> = Vec::new();
+  for edge in edges {
+    if !actions.contains(&&edge.action) {
+      actions.push(&edge.action);
+    }
+  }
+
+  let impls = states.iter().map(|state| {
+    // Actions that aren't a transition of the state leave it as it is
+    let methods = actions.iter().map(|action| {
+      let body = match edges
+        .iter()
+        .find(|edge| edge.from == *state && edge.action == **action)
+      {
+        Some(edge) => {
+          let to = &edge.to;
+          quote!(Box::new(#to))
+        }
+        None => quote!(self),
+      };
+      quote! {
+        fn #action(self: Box<Self>) -> Box<dyn State> {
+          #body
+        }
+      }
+    });
+    quote! {
+      pub struct #state;
+
+      impl State for #state {
+        #(#methods)*
+        fn state(&self) -> #name {
+          #name::#state
+        }
+      }
+    }
+  });
+
+  // Without data the Machine can be made from nothing
+  let machine = match data {
+    Some(data) => quote! {
+      pub struct Machine {
+        state: Option<Box<dyn State>>,
+        pub data: #data,
+      }
+
+      impl Machine {
+        pub fn new(data: #data) -> Machine {
+          Machine {
+            state: Some(Box::new(#initial)),
+            data,
+          }
+        }
+      }
+    },
+    None => quote! {
+      pub struct Machine {
+        state: Option<Box<dyn State>>,
+      }
+
+      impl Default for Machine {
+        fn default() -> Machine {
+          Machine::new()
+        }
+      }
+
+      impl Machine {
+        pub fn new() -> Machine {
+          Machine {
+            state: Some(Box::new(#initial)),
+          }
+        }
+      }
+    },
+  };
+
+  let action_names = actions.iter().map(|action| action.to_string());
+  quote! {
+    pub trait State {
+      #(fn #actions(self: Box<Self>) -> Box<dyn State>;)*
+      fn state(&self) -> #name;
+    }
+
+    #(#impls)*
+
+    #machine
+
+    impl Machine {
+      pub fn state(&self) -> #name {
+        self.state.as_ref().unwrap().state()
+      }
+
+      #(
+        pub fn #actions(&mut self) -> Result<(), ::state_machine::TransitionError> {
+          let from = ::state_machine::StateMachine::name(&self.state());
+          // take takes ownership
+          if let Some(s) = self.state.take() {
+            self.state = Some(s.#actions())
+          }
+          match <#name as ::state_machine::StateMachine>::next(from, #action_names) {
+            Some(_) => Ok(()),
+            None => Err(::state_machine::TransitionError {
+              state: from,
+              action: #action_names,
+            }),
+          }
+        }
+      )*
+    }
+  }
+}
+
+fn typestate(
+  name: &Ident,
+  states: &[Ident],
+  initial: &Ident,
+  edges: &[Edge],
+  data: &syn::Type,
+) -> proc_macro2::TokenStream {
+  let structs = states.iter().map(|state| {
+    // Only the initial state can be made from scratch
+    let new = if state == initial {
+      quote! {
+        pub fn new(data: #data) -> #state {
+          #state { data }
+        }
+      }
+    } else {
+      quote!()
+    };
+    let methods = edges.iter().filter(|edge| edge.from == *state).map(|edge| {
+      let action = &edge.action;
+      let to = &edge.to;
+      quote! {
+        pub fn #action(self) -> #to {
+          #to { data: self.data }
+        }
+      }
+    });
+    quote! {
+      pub struct #state {
+        pub data: #data,
+      }
+
+      impl #state {
+        #new
+
+        pub fn state(&self) -> #name {
+          #name::#state
+        }
+
+        #(#methods)*
+      }
+    }
+  });
+  quote!(#(#structs)*)
+}
+
+#[proc_macro_derive(StateMachine, attributes(state_machine, initial, transition))]
+pub fn state_machine_derive(input: TokenStream) -> TokenStream {
+  // Construct a representation of Rust code as a syntax tree
+  // that we can manipulate
+  let ast = syn::parse_macro_input!(input as syn::DeriveInput);
+
+  // Mistakes in the attributes become compile errors pointing at them
+  impl_state_machine(&ast)
+    .unwrap_or_else(syn::Error::into_compile_error)
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn module_names() {
+    assert_eq!("order_state", snake_case("OrderState"));
+    assert_eq!("http_state", snake_case("HTTPState"));
+    assert_eq!("state_http", snake_case("StateHTTP"));
+    assert_eq!("io2_step", snake_case("IO2Step"));
+    assert_eq!("a", snake_case("A"));
+    assert_eq!("paid_in_full", snake_case("Paid_InFull"));
+  }
+}
@@ -0,0 +1,13 @@
+// Mistakes in the attributes, each has to fail with the message in its .stderr
+#[test]
+fn rejects_mistakes() {
+  let t = trybuild::TestCases::new();
+  t.compile_fail("tests/ui/*.rs");
+}
+
+// What the workflows crate doesn't use has to compile too
+#[test]
+fn generates_every_style() {
+  let t = trybuild::TestCases::new();
+  t.pass("tests/pass/*.rs");
+}
@@ -0,0 +1,18 @@
+use state_machine::StateMachine;
+use state_machine_derive::StateMachine;
+
+#[derive(Debug, PartialEq, StateMachine)]
+enum Door {
+  #[transition(open -> Open)]
+  Closed,
+  #[transition(close -> Closed)]
+  Open,
+}
+
+fn main() {
+  let mut door = door_machine::Machine::default();
+  assert_eq!(Ok(()), door.open());
+  assert!(door.open().is_err());
+  assert_eq!(Door::Open, door.state());
+  assert_eq!("Open", door.state().name());
+}
@@ -0,0 +1,11 @@
+use state_machine_derive::StateMachine;
+
+#[derive(StateMachine)]
+enum Door {
+  #[transition(open -> Open)]
+  Closed,
+  #[initial(yes)]
+  Open,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use state_machine_derive::StateMachine;
+
+#[derive(StateMachine)]
+enum Door {
+  #[transition(open -> Machine)]
+  Closed,
+  Machine,
+}
+
+fn main() {}
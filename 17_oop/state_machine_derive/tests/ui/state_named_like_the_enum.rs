@@ -0,0 +1,10 @@
+use state_machine_derive::StateMachine;
+
+#[derive(StateMachine)]
+enum Post {
+  #[transition(publish -> Post)]
+  Draft,
+  Post,
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use state_machine_derive::StateMachine;
+
+#[derive(StateMachine)]
+enum Door {
+  #[initial]
+  #[transition(open -> Open)]
+  Closed,
+  #[initial]
+  #[transition(close -> Closed)]
+  Open,
+}
+
+fn main() {}
@@ -0,0 +1,3 @@
+// Workflows built with #[derive(StateMachine)], see state_machine_derive
+pub mod order;
+pub mod ticket;
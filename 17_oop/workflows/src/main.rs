@@ -0,0 +1,9 @@
+use state_machine::StateMachine;
+use workflows::order::OrderState;
+use workflows::ticket::TicketStatus;
+
+// cargo run | dot -Tsvg > workflows.svg draws both diagrams
+fn main() {
+  print!("{}", OrderState::dot());
+  print!("{}", TicketStatus::dot());
+}
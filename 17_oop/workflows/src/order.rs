@@ -0,0 +1,84 @@
+use state_machine_derive::StateMachine;
+
+pub struct Order {
+  pub items: Vec<String>,
+  pub total_cents: u64,
+}
+
+// The boxed version, order_state_machine::Machine holds a Box<dyn State>
+// and the Order
+#[derive(Debug, Clone, Copy, PartialEq, StateMachine)]
+#[state_machine(data = "Order")]
+pub enum OrderState {
+  #[initial]
+  #[transition(pay -> Paid, cancel -> Cancelled)]
+  Placed,
+  #[transition(ship -> Shipped, refund -> Refunded)]
+  Paid,
+  #[transition(deliver -> Delivered)]
+  Shipped,
+  Delivered,
+  Refunded,
+  Cancelled,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::order_state_machine::Machine;
+  use super::*;
+  use state_machine::StateMachine;
+  use state_machine::TransitionError;
+
+  #[test]
+  fn goes_through_the_transitions() {
+    let mut order = Machine::new(Order {
+      items: vec![String::from("teapot")],
+      total_cents: 2500,
+    });
+    assert_eq!(OrderState::Placed, order.state());
+    order.data.items.push(String::from("cups"));
+    assert_eq!(Ok(()), order.pay());
+    assert_eq!(Ok(()), order.ship());
+    assert_eq!(OrderState::Shipped, order.state());
+
+    // Too late to cancel, the order stays where it is
+    assert_eq!(
+      Err(TransitionError {
+        state: "Shipped",
+        action: "cancel"
+      }),
+      order.cancel()
+    );
+    assert_eq!(OrderState::Shipped, order.state());
+    assert_eq!(Ok(()), order.deliver());
+    assert_eq!("Delivered", order.state().name());
+    assert_eq!(vec!["teapot", "cups"], order.data.items);
+    assert_eq!(2500, order.data.total_cents);
+  }
+
+  #[test]
+  fn exports_dot() {
+    assert_eq!(Some("Refunded"), OrderState::next("Paid", "refund"));
+    assert_eq!(None, OrderState::next("Placed", "refund"));
+    assert_eq!(
+      "\
+digraph \"OrderState\" {
+  \"(start)\" [shape=point];
+  \"Placed\";
+  \"Paid\";
+  \"Shipped\";
+  \"Delivered\";
+  \"Refunded\";
+  \"Cancelled\";
+  \"(start)\" -> \"Placed\";
+  \"Placed\" -> \"Paid\" [label=\"pay\"];
+  \"Placed\" -> \"Cancelled\" [label=\"cancel\"];
+  \"Paid\" -> \"Shipped\" [label=\"ship\"];
+  \"Paid\" -> \"Refunded\" [label=\"refund\"];
+  \"Shipped\" -> \"Delivered\" [label=\"deliver\"];
+}
+",
+      OrderState::dot()
+    );
+  }
+}
@@ -0,0 +1,52 @@
+use state_machine_derive::StateMachine;
+
+pub struct Ticket {
+  pub title: String,
+  pub assignee: Option<String>,
+}
+
+// The type-state version, each state of ticket_status_machine is a type
+// carrying the Ticket, so resolving an open ticket doesn't compile
+#[derive(Debug, PartialEq, StateMachine)]
+#[state_machine(typestate, data = "Ticket")]
+pub enum TicketStatus {
+  #[transition(assign -> InProgress)]
+  Open,
+  #[transition(resolve -> Resolved, unassign -> Open)]
+  InProgress,
+  #[transition(close -> Closed, reopen -> Open)]
+  Resolved,
+  Closed,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::ticket_status_machine::Open;
+  use super::*;
+  use state_machine::StateMachine;
+
+  #[test]
+  fn carries_the_ticket_through() {
+    let ticket = Open::new(Ticket {
+      title: String::from("The frog is gone"),
+      assignee: None,
+    });
+    assert_eq!(TicketStatus::Open, ticket.state());
+
+    let mut ticket = ticket.assign();
+    ticket.data.assignee = Some(String::from("ann"));
+    let ticket = ticket.resolve().reopen().assign().resolve();
+    assert_eq!(TicketStatus::Resolved, ticket.state());
+
+    let ticket = ticket.close();
+    assert_eq!("Closed", ticket.state().name());
+    assert_eq!("The frog is gone", ticket.data.title);
+    assert_eq!(Some(String::from("ann")), ticket.data.assignee);
+  }
+
+  #[test]
+  fn first_state_is_initial_by_default() {
+    assert_eq!("Open", TicketStatus::INITIAL);
+    assert_eq!(5, TicketStatus::TRANSITIONS.len());
+  }
+}
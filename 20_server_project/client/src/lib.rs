@@ -0,0 +1,419 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// The client speaks the same HTTP as the server
+pub use shutdown_cleanup::http::Headers;
+pub use shutdown_cleanup::http::Request;
+pub use shutdown_cleanup::http::Response;
+use shutdown_cleanup::http;
+
+const CHUNK_SIZE: usize = 8 * 1024;
+
+type Connection = BufReader<TcpStream>;
+
+/// A blocking HTTP/1.1 client.
+///
+/// Connections are kept open after a response and reused by the next
+/// requests to the same address, unless one side asked to close them.
+pub struct Client {
+  timeout: Option<Duration>,
+  max_idle: usize,
+  idle: Mutex<HashMap<String, Vec<Connection>>>,
+}
+
+impl Default for Client {
+  fn default() -> Client {
+    Client::new()
+  }
+}
+
+impl Client {
+  pub fn new() -> Client {
+    Client {
+      timeout: Some(Duration::from_secs(30)),
+      max_idle: 4,
+      idle: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /// Sets how long connecting, and each read or write, may take before
+  /// failing. `None` waits forever.
+  pub fn timeout(mut self, timeout: Option<Duration>) -> Client {
+    self.timeout = timeout;
+    self
+  }
+
+  /// Sets how many idle connections are kept per address.
+  pub fn max_idle(mut self, max_idle: usize) -> Client {
+    self.max_idle = max_idle;
+    self
+  }
+
+  pub fn get(&self, url: &str) -> RequestBuilder<'_> {
+    self.request("GET", url)
+  }
+
+  pub fn post(&self, url: &str) -> RequestBuilder<'_> {
+    self.request("POST", url)
+  }
+
+  /// Starts a request, the url must look like `http://host[:port][/path]`.
+  pub fn request(&self, method: &str, url: &str) -> RequestBuilder<'_> {
+    RequestBuilder {
+      client: self,
+      url: url.to_string(),
+      request: Request::new(method, "/"),
+      reader: None,
+    }
+  }
+
+  /// Number of open connections waiting for a request.
+  pub fn idle_connections(&self) -> usize {
+    self.idle.lock().unwrap().values().map(Vec::len).sum()
+  }
+
+  // Returns an idle connection if there is one still open, the flag tells
+  // whether it was reused
+  fn connect(&self, addr: &str) -> io::Result<(Connection, bool)> {
+    let idle = self.idle.lock().unwrap().get_mut(addr).and_then(Vec::pop);
+    if let Some(connection) = idle {
+      if is_open(&connection) {
+        return Ok((connection, true));
+      }
+    }
+    Ok((self.open(addr)?, false))
+  }
+
+  fn open(&self, addr: &str) -> io::Result<Connection> {
+    let mut last_error = None;
+    for addr in addr.to_socket_addrs()? {
+      let stream = match self.timeout {
+        Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+        None => TcpStream::connect(addr),
+      };
+      match stream {
+        Ok(stream) => {
+          stream.set_read_timeout(self.timeout)?;
+          stream.set_write_timeout(self.timeout)?;
+          return Ok(BufReader::new(stream));
+        }
+        Err(e) => last_error = Some(e),
+      }
+    }
+    Err(last_error.unwrap_or_else(|| invalid_input("address resolved to nothing")))
+  }
+
+  fn release(&self, addr: String, connection: Connection) {
+    let mut idle = self.idle.lock().unwrap();
+    let connections = idle.entry(addr).or_default();
+    if connections.len() < self.max_idle {
+      connections.push(connection);
+    }
+  }
+}
+
+pub struct RequestBuilder<'a> {
+  client: &'a Client,
+  url: String,
+  request: Request,
+  reader: Option<Box<dyn Read + 'a>>,
+}
+
+impl<'a> RequestBuilder<'a> {
+  pub fn header(mut self, name: &str, value: &str) -> RequestBuilder<'a> {
+    self.request.headers.set(name, value);
+    self
+  }
+
+  /// Sends the body with a Content-Length.
+  pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> RequestBuilder<'a> {
+    self.request.body = body.into();
+    self.request.headers.set("Content-Length", &self.request.body.len().to_string());
+    self.request.headers.remove("Transfer-Encoding");
+    self.reader = None;
+    self
+  }
+
+  /// Streams the body from the reader in chunks, for bodies whose size isn't
+  /// known in advance.
+  pub fn body_reader<R: Read + 'a>(mut self, reader: R) -> RequestBuilder<'a> {
+    self.request.body.clear();
+    self.request.headers.remove("Content-Length");
+    self.request.headers.set("Transfer-Encoding", "chunked");
+    self.reader = Some(Box::new(reader));
+    self
+  }
+
+  /// Sends the request and waits for the whole response.
+  ///
+  /// # Errors
+  ///
+  /// Returns an `InvalidInput` error if the url isn't supported, `TimedOut`
+  /// or `WouldBlock` if the server took longer than the timeout, and the
+  /// underlying error if the connection failed.
+  pub fn send(mut self) -> io::Result<Response> {
+    let (addr, host, target) = parse_url(&self.url)?;
+    self.request.target = target;
+    if self.request.headers.get("Host").is_none() {
+      self.request.headers.set("Host", &host);
+    }
+
+    let (mut connection, reused) = self.client.connect(&addr)?;
+    let mut result = exchange(&mut connection, &self.request, self.reader.as_mut());
+    // The server may have closed a reused connection just before we sent the
+    // request. Sending it again mustn't apply it twice, so only idempotent
+    // requests are retried, or ones the server can't have received whole. A
+    // body from a reader can't be sent twice though
+    if let Err(failure) = &result {
+      let safe = is_idempotent(&self.request.method) || !failure.written;
+      if reused && self.reader.is_none() && safe {
+        connection = self.client.open(&addr)?;
+        result = exchange(&mut connection, &self.request, None);
+      }
+    }
+    let response = result.map_err(|failure| failure.error)?;
+
+    if can_reuse(&self.request, &response) {
+      self.client.release(addr, connection);
+    }
+    Ok(response)
+  }
+}
+
+// Why an exchange failed, and whether the whole request was sent before
+struct Failure {
+  error: io::Error,
+  written: bool,
+}
+
+fn exchange(
+  connection: &mut Connection,
+  request: &Request,
+  reader: Option<&mut Box<dyn Read + '_>>,
+) -> Result<Response, Failure> {
+  let written = {
+    let mut writer = BufWriter::new(connection.get_mut());
+    match reader {
+      Some(reader) => request
+        .write_head_to(&mut writer)
+        .and_then(|()| http::write_chunked(reader, &mut writer, CHUNK_SIZE)),
+      None => request.write_to(&mut writer),
+    }
+  };
+  written.map_err(|error| Failure { error, written: false })?;
+  Response::read_from(connection).map_err(|error| Failure { error, written: true })
+}
+
+// Sending these twice has the same effect as sending them once
+fn is_idempotent(method: &str) -> bool {
+  matches!(method, "GET" | "HEAD" | "PUT" | "DELETE" | "OPTIONS")
+}
+
+fn can_reuse(request: &Request, response: &Response) -> bool {
+  let close = |headers: &Headers| {
+    headers
+      .get("Connection")
+      .is_some_and(|c| c.eq_ignore_ascii_case("close"))
+  };
+  // Without a length the body went until the server closed the connection
+  let delimited = response.headers.get("Content-Length").is_some()
+    || matches!(response.status, 100..=199 | 204 | 304);
+
+  response.version == "HTTP/1.1" && delimited && !close(&request.headers) && !close(&response.headers)
+}
+
+// An idle connection has nothing to read, unless the server closed it
+fn is_open(connection: &Connection) -> bool {
+  let stream = connection.get_ref();
+  if !connection.buffer().is_empty() || stream.set_nonblocking(true).is_err() {
+    return false;
+  }
+  let open = match stream.peek(&mut [0]) {
+    Err(e) => e.kind() == io::ErrorKind::WouldBlock,
+    Ok(_) => false,
+  };
+  open && stream.set_nonblocking(false).is_ok()
+}
+
+// Splits `http://host:port/path` into the address to connect to, the Host
+// header and the request target
+fn parse_url(url: &str) -> io::Result<(String, String, String)> {
+  let rest = url
+    .strip_prefix("http://")
+    .ok_or_else(|| invalid_input("only http:// urls are supported"))?;
+  let (authority, target) = match rest.find(['/', '?']) {
+    Some(i) if rest[i..].starts_with('?') => (&rest[..i], format!("/{}", &rest[i..])),
+    Some(i) => (&rest[..i], rest[i..].to_string()),
+    None => (rest, String::from("/")),
+  };
+  if authority.is_empty() {
+    return Err(invalid_input("url without a host"));
+  }
+
+  let has_port = match authority.rfind(':') {
+    Some(i) => !authority[i..].contains(']'),
+    None => false,
+  };
+  let addr = if has_port {
+    authority.to_string()
+  } else {
+    format!("{}:80", authority)
+  };
+  Ok((addr, authority.to_string(), target))
+}
+
+fn invalid_input(message: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::net::TcpListener;
+  use std::sync::atomic::AtomicUsize;
+  use std::sync::atomic::Ordering;
+  use std::sync::Arc;
+  use std::thread;
+
+  // A keep-alive server echoing the requests, it returns its address and the
+  // number of connections it accepted
+  fn server() -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&connections);
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        counter.fetch_add(1, Ordering::SeqCst);
+        thread::spawn(move || serve(stream.unwrap()));
+      }
+    });
+    (addr, connections)
+  }
+
+  fn serve(mut stream: TcpStream) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    while let Ok(request) = Request::read_from(&mut reader) {
+      let echo = format!(
+        "{} {} {} {}",
+        request.method,
+        request.target,
+        request.headers.get("X-Test").unwrap_or("-"),
+        String::from_utf8_lossy(&request.body)
+      );
+      match request.path() {
+        "/chunked" => {
+          stream.write_all(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n").unwrap();
+          http::write_chunked(&mut echo.as_bytes(), &mut stream, 3).unwrap();
+        }
+        "/close" => {
+          let mut response = Response::new(200, "OK").with_body(echo.into_bytes());
+          response.headers.set("Connection", "close");
+          response.write_to(&mut stream).unwrap();
+          return;
+        }
+        "/hang" => thread::sleep(Duration::from_secs(2)),
+        // Closes the connection without answering, as if it had timed out
+        "/drop" => return,
+        _ => Response::new(200, "OK").with_body(echo.into_bytes()).write_to(&mut stream).unwrap(),
+      }
+    }
+  }
+
+  fn body(response: Response) -> String {
+    String::from_utf8(response.body).unwrap()
+  }
+
+  #[test]
+  fn get_and_post() {
+    let (addr, _) = server();
+    let client = Client::new();
+
+    let response = client.get(&format!("http://{}/hello?name=ferris", addr)).header("X-Test", "1").send().unwrap();
+    assert_eq!(200, response.status);
+    assert_eq!("GET /hello?name=ferris 1 ", body(response));
+
+    let response = client.post(&format!("http://{}/form", addr)).body("a=1&b=2").send().unwrap();
+    assert_eq!("POST /form - a=1&b=2", body(response));
+  }
+
+  #[test]
+  fn chunked_bodies() {
+    let (addr, _) = server();
+    let client = Client::new();
+
+    let upload = vec![b'x'; CHUNK_SIZE * 2 + 10];
+    let response = client.post(&format!("http://{}/upload", addr)).body_reader(&upload[..]).send().unwrap();
+    assert_eq!(format!("POST /upload - {}", String::from_utf8(upload).unwrap()), body(response));
+
+    let response = client.get(&format!("http://{}/chunked", addr)).send().unwrap();
+    assert_eq!("GET /chunked - ", body(response));
+  }
+
+  #[test]
+  fn reuses_connections() {
+    let (addr, connections) = server();
+    let client = Client::new();
+
+    for _ in 0..3 {
+      client.get(&format!("http://{}/", addr)).send().unwrap();
+    }
+    client.get(&format!("http://{}/chunked", addr)).send().unwrap();
+    assert_eq!(1, connections.load(Ordering::SeqCst));
+    assert_eq!(1, client.idle_connections());
+
+    // Closed by the server, then a new one is opened
+    client.get(&format!("http://{}/close", addr)).send().unwrap();
+    assert_eq!(0, client.idle_connections());
+    client.get(&format!("http://{}/", addr)).send().unwrap();
+    assert_eq!(2, connections.load(Ordering::SeqCst));
+  }
+
+  #[test]
+  fn retries_only_idempotent_requests() {
+    let (addr, connections) = server();
+    let client = Client::new();
+
+    // The dropped GET is sent again on a new connection, which drops it too
+    client.get(&format!("http://{}/", addr)).send().unwrap();
+    assert!(client.get(&format!("http://{}/drop", addr)).send().is_err());
+    assert_eq!(2, connections.load(Ordering::SeqCst));
+
+    // The server may have acted on the POST, so it isn't sent again
+    client.get(&format!("http://{}/", addr)).send().unwrap();
+    let error = client.post(&format!("http://{}/drop", addr)).body("a=1").send().unwrap_err();
+    assert_eq!(io::ErrorKind::UnexpectedEof, error.kind());
+    assert_eq!(3, connections.load(Ordering::SeqCst));
+  }
+
+  #[test]
+  fn times_out() {
+    let (addr, _) = server();
+    let client = Client::new().timeout(Some(Duration::from_millis(100)));
+
+    let error = client.get(&format!("http://{}/hang", addr)).send().unwrap_err();
+    assert!(matches!(error.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut));
+  }
+
+  #[test]
+  fn urls() {
+    assert_eq!(
+      ("localhost:7878".to_string(), "localhost:7878".to_string(), "/sleep".to_string()),
+      parse_url("http://localhost:7878/sleep").unwrap()
+    );
+    assert_eq!(
+      ("example.com:80".to_string(), "example.com".to_string(), "/?q=1".to_string()),
+      parse_url("http://example.com?q=1").unwrap()
+    );
+    assert_eq!("[::1]:80", parse_url("http://[::1]").unwrap().0);
+    assert_eq!(io::ErrorKind::InvalidInput, parse_url("https://example.com").unwrap_err().kind());
+    assert_eq!(io::ErrorKind::InvalidInput, parse_url("http:///").unwrap_err().kind());
+  }
+}
@@ -0,0 +1,38 @@
+use std::env;
+use std::io;
+use std::io::prelude::*;
+use std::process;
+
+use client::Client;
+
+/*
+  cargo run -- http://127.0.0.1:7878/
+  prints the response of the server, and with a body
+  cargo run -- http://127.0.0.1:7878/ "name=Ferris"
+  it's sent as a POST
+*/
+fn main() {
+  let args: Vec<String> = env::args().collect();
+  if args.len() < 2 {
+    eprintln!("Usage: {} <url> [body]", args[0]);
+    process::exit(1);
+  }
+
+  let client = Client::new();
+  let request = match args.get(2) {
+    Some(body) => client.post(&args[1]).body(body.as_bytes()),
+    None => client.get(&args[1]),
+  };
+
+  let response = request.send().unwrap_or_else(|err| {
+    eprintln!("Request failed: {}", err);
+    process::exit(1);
+  });
+
+  println!("{} {} {}", response.version, response.status, response.reason);
+  for (name, value) in response.headers.iter() {
+    println!("{}: {}", name, value);
+  }
+  println!();
+  io::stdout().write_all(&response.body).unwrap();
+}
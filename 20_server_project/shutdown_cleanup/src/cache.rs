@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// A thread-safe LRU cache of file contents, bounded by its total size in bytes.
+///
+/// Every lookup compares the file's modification time with the cached one, so
+/// a file edited on disk is read again instead of being served stale.
+pub struct FileCache {
+  capacity: usize,
+  entries: Mutex<Entries>,
+  hits: AtomicUsize,
+  misses: AtomicUsize,
+}
+
+struct Entry {
+  contents: Arc<Vec<u8>>,
+  modified: SystemTime,
+  last_used: u64,
+}
+
+struct Entries {
+  files: HashMap<PathBuf, Entry>,
+  // Maps the last use of an entry to its path, the oldest one comes first
+  recency: BTreeMap<u64, PathBuf>,
+  size: usize,
+  clock: u64,
+}
+
+impl Entries {
+  fn tick(&mut self) -> u64 {
+    self.clock += 1;
+    self.clock
+  }
+
+  fn get(&mut self, path: &Path, modified: SystemTime) -> Option<Arc<Vec<u8>>> {
+    let stale = match self.files.get(path) {
+      Some(entry) => entry.modified != modified,
+      None => return None,
+    };
+    if stale {
+      self.remove(path);
+      return None;
+    }
+
+    let now = self.tick();
+    let entry = self.files.get_mut(path).unwrap();
+    self.recency.remove(&entry.last_used);
+    self.recency.insert(now, path.to_path_buf());
+    entry.last_used = now;
+    Some(Arc::clone(&entry.contents))
+  }
+
+  fn insert(&mut self, path: &Path, entry: Entry) {
+    self.remove(path);
+    self.size += entry.contents.len();
+    self.recency.insert(entry.last_used, path.to_path_buf());
+    self.files.insert(path.to_path_buf(), entry);
+  }
+
+  fn remove(&mut self, path: &Path) {
+    if let Some(entry) = self.files.remove(path) {
+      self.recency.remove(&entry.last_used);
+      self.size -= entry.contents.len();
+    }
+  }
+
+  fn evict_oldest(&mut self) {
+    let oldest = self.recency.values().next().cloned();
+    if let Some(path) = oldest {
+      self.remove(&path);
+    }
+  }
+}
+
+impl FileCache {
+  /// Create a new FileCache.
+  ///
+  /// The capacity is the maximum number of bytes kept in memory, files larger
+  /// than that are always read from disk.
+  pub fn new(capacity: usize) -> FileCache {
+    FileCache {
+      capacity,
+      entries: Mutex::new(Entries {
+        files: HashMap::new(),
+        recency: BTreeMap::new(),
+        size: 0,
+        clock: 0,
+      }),
+      hits: AtomicUsize::new(0),
+      misses: AtomicUsize::new(0),
+    }
+  }
+
+  /// Returns the contents of the file, from memory when the cached copy is
+  /// still up to date and from disk otherwise.
+  ///
+  /// # Errors
+  ///
+  /// Returns the error of the underlying `fs` call if the file can't be read.
+  pub fn get<P: AsRef<Path>>(&self, path: P) -> io::Result<Arc<Vec<u8>>> {
+    let path = path.as_ref();
+    let modified = fs::metadata(path)?.modified()?;
+
+    if let Some(contents) = self.entries.lock().unwrap().get(path, modified) {
+      self.hits.fetch_add(1, Ordering::Relaxed);
+      return Ok(contents);
+    }
+    self.misses.fetch_add(1, Ordering::Relaxed);
+
+    let contents = Arc::new(fs::read(path)?);
+    if contents.len() <= self.capacity {
+      let mut entries = self.entries.lock().unwrap();
+      while entries.size + contents.len() > self.capacity {
+        entries.evict_oldest();
+      }
+      let last_used = entries.tick();
+      entries.insert(path, Entry { contents: Arc::clone(&contents), modified, last_used });
+    }
+    Ok(contents)
+  }
+
+  /// Number of lookups served from memory.
+  pub fn hits(&self) -> usize {
+    self.hits.load(Ordering::Relaxed)
+  }
+
+  /// Number of lookups that had to read the file from disk.
+  pub fn misses(&self) -> usize {
+    self.misses.load(Ordering::Relaxed)
+  }
+
+  /// Number of bytes currently held in memory.
+  pub fn size(&self) -> usize {
+    self.entries.lock().unwrap().size
+  }
+
+  /// Number of files currently held in memory.
+  pub fn len(&self) -> usize {
+    self.entries.lock().unwrap().files.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs::File;
+  use std::time::Duration;
+
+  fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("shutdown_cleanup_cache_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  #[test]
+  fn hits_and_misses() {
+    let dir = temp_dir("hits");
+    let path = dir.join("hello.html");
+    fs::write(&path, "Hello!").unwrap();
+
+    let cache = FileCache::new(1024);
+    assert_eq!(b"Hello!", &cache.get(&path).unwrap()[..]);
+    assert_eq!(b"Hello!", &cache.get(&path).unwrap()[..]);
+    assert_eq!(1, cache.misses());
+    assert_eq!(1, cache.hits());
+    assert_eq!(6, cache.size());
+
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn evicts_least_recently_used() {
+    let dir = temp_dir("evict");
+    for name in &["a", "b", "c"] {
+      fs::write(dir.join(name), "0123456789").unwrap();
+    }
+
+    let cache = FileCache::new(25);
+    cache.get(dir.join("a")).unwrap();
+    cache.get(dir.join("b")).unwrap();
+    // a is now more recent than b
+    cache.get(dir.join("a")).unwrap();
+    cache.get(dir.join("c")).unwrap();
+    assert_eq!(2, cache.len());
+    assert_eq!(20, cache.size());
+
+    let misses = cache.misses();
+    cache.get(dir.join("a")).unwrap();
+    cache.get(dir.join("c")).unwrap();
+    assert_eq!(misses, cache.misses());
+    cache.get(dir.join("b")).unwrap();
+    assert_eq!(misses + 1, cache.misses());
+
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn skips_files_larger_than_capacity() {
+    let dir = temp_dir("large");
+    let path = dir.join("large");
+    fs::write(&path, "0123456789").unwrap();
+
+    let cache = FileCache::new(5);
+    assert_eq!(10, cache.get(&path).unwrap().len());
+    assert!(cache.is_empty());
+
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn invalidates_modified_files() {
+    let dir = temp_dir("modified");
+    let path = dir.join("hello.html");
+    fs::write(&path, "Hello!").unwrap();
+
+    let cache = FileCache::new(1024);
+    cache.get(&path).unwrap();
+
+    fs::write(&path, "Bonjour!").unwrap();
+    let later = SystemTime::now() + Duration::from_secs(10);
+    File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+
+    assert_eq!(b"Bonjour!", &cache.get(&path).unwrap()[..]);
+    assert_eq!(2, cache.misses());
+    assert_eq!(8, cache.size());
+
+    fs::remove_dir_all(dir).unwrap();
+  }
+}
@@ -0,0 +1,183 @@
+use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::iter;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The server settings, read from `key = value` lines.
+///
+/// ```text
+/// # Comments start with a hash
+/// root = .
+/// route / = hello.html
+/// not_found = 404.html
+/// cache_size = 1048576
+/// rate_limit_burst = 20
+/// rate_limit_refill = 5
+/// upstreams = /api=127.0.0.1:9000,127.0.0.1:9001
+/// max_connections = 2
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+  /// The directory the pages are read from
+  pub root: PathBuf,
+  /// Request paths and the pages they serve, relative to the root
+  pub routes: Vec<(String, PathBuf)>,
+  pub not_found: PathBuf,
+  pub cache_size: usize,
+  pub rate_limit_burst: u32,
+  pub rate_limit_refill: f64,
+  /// The proxy routes, see `Proxy::parse`
+  pub upstreams: String,
+  /// The server shuts down after this many connections
+  pub max_connections: Option<usize>,
+}
+
+impl Default for Config {
+  fn default() -> Config {
+    Config {
+      root: PathBuf::from("."),
+      routes: vec![(String::from("/"), PathBuf::from("hello.html"))],
+      not_found: PathBuf::from("404.html"),
+      cache_size: 1024 * 1024,
+      rate_limit_burst: 20,
+      rate_limit_refill: 5.0,
+      upstreams: String::new(),
+      max_connections: None,
+    }
+  }
+}
+
+impl Config {
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, Box<dyn Error>> {
+    Config::parse(&fs::read_to_string(path)?)
+  }
+
+  /// Parses the settings, the missing ones keep their default value. Routes
+  /// replace the default ones as soon as there is one.
+  ///
+  /// # Errors
+  ///
+  /// Returns an error naming the line of an unknown key or an invalid value.
+  pub fn parse(contents: &str) -> Result<Config, Box<dyn Error>> {
+    let mut config = Config::default();
+    let mut routes = Vec::new();
+
+    for (number, line) in contents.lines().enumerate() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let error = |message: &str| format!("line {}: {}", number + 1, message);
+      let (key, value) = line.split_once('=').ok_or_else(|| error("expected key = value"))?;
+      let (key, value) = (key.trim(), value.trim());
+
+      if let Some(path) = key.strip_prefix("route ") {
+        routes.push((path.trim().to_string(), PathBuf::from(value)));
+        continue;
+      }
+      match key {
+        "root" => config.root = PathBuf::from(value),
+        "not_found" => config.not_found = PathBuf::from(value),
+        "cache_size" => config.cache_size = value.parse().map_err(|_| error("invalid cache_size"))?,
+        "rate_limit_burst" => match value.parse() {
+          Ok(burst) if burst > 0 => config.rate_limit_burst = burst,
+          _ => return Err(error("invalid rate_limit_burst").into()),
+        },
+        "rate_limit_refill" => match value.parse() {
+          Ok(refill) if refill > 0.0 => config.rate_limit_refill = refill,
+          _ => return Err(error("invalid rate_limit_refill").into()),
+        },
+        "upstreams" => config.upstreams = value.to_string(),
+        "max_connections" => {
+          config.max_connections = Some(value.parse().map_err(|_| error("invalid max_connections"))?)
+        }
+        _ => return Err(error(&format!("unknown key {}", key)).into()),
+      }
+    }
+
+    if !routes.is_empty() {
+      config.routes = routes;
+    }
+    Ok(config)
+  }
+
+  /// Checks that every page, the not found one included, is a file that can be
+  /// read, so a config pointing at a missing one is refused up front.
+  ///
+  /// # Errors
+  ///
+  /// Returns an error naming the first page that can't be read.
+  pub fn check_pages(&self) -> Result<(), Box<dyn Error>> {
+    let pages = self.routes.iter().map(|(_, file)| file).chain(iter::once(&self.not_found));
+    for page in pages {
+      let path = self.root.join(page);
+      match File::open(&path).and_then(|file| file.metadata()) {
+        Ok(metadata) if metadata.is_file() => {}
+        Ok(_) => return Err(format!("{} is not a file", path.display()).into()),
+        Err(e) => return Err(format!("can't read {}: {}", path.display(), e).into()),
+      }
+    }
+    Ok(())
+  }
+
+  /// The file serving this path, with the status of the response.
+  pub fn page(&self, path: &str) -> (u16, PathBuf) {
+    match self.routes.iter().find(|(route, _)| route == path) {
+      Some((_, file)) => (200, self.root.join(file)),
+      None => (404, self.root.join(&self.not_found)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse() {
+    let config = Config::parse(
+      "\
+# Pages
+root = public
+route / = index.html
+route /about = about.html
+
+cache_size = 2048
+rate_limit_refill = 0.5
+upstreams = /api=127.0.0.1:9000",
+    )
+    .unwrap();
+
+    assert_eq!(PathBuf::from("public"), config.root);
+    assert_eq!(2, config.routes.len());
+    assert_eq!(2048, config.cache_size);
+    assert_eq!(20, config.rate_limit_burst);
+    assert_eq!(0.5, config.rate_limit_refill);
+    assert_eq!("/api=127.0.0.1:9000", config.upstreams);
+    assert_eq!(None, config.max_connections);
+
+    assert_eq!((200, PathBuf::from("public/about.html")), config.page("/about"));
+    assert_eq!((404, PathBuf::from("public/404.html")), config.page("/missing"));
+  }
+
+  #[test]
+  fn errors() {
+    let error = Config::parse("root = .\ncache_size = big").unwrap_err();
+    assert_eq!("line 2: invalid cache_size", error.to_string());
+
+    let error = Config::parse("port = 80").unwrap_err();
+    assert_eq!("line 1: unknown key port", error.to_string());
+
+    assert!(Config::parse("root").is_err());
+    assert!(Config::parse("rate_limit_burst = 0").is_err());
+
+    let error = Config::parse("route / = missing.html").unwrap().check_pages().unwrap_err();
+    assert!(error.to_string().starts_with("can't read ./missing.html"));
+    let error = Config::parse("route / = src").unwrap().check_pages().unwrap_err();
+    assert_eq!("./src is not a file", error.to_string());
+    assert!(Config::default().check_pages().is_ok());
+    assert_eq!(Config::default(), Config::parse("").unwrap());
+  }
+}
@@ -0,0 +1,299 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::form;
+use crate::form::Form;
+use crate::form::FormError;
+use crate::http::Request;
+use crate::http::Response;
+
+/// Why a request couldn't be turned into what a handler expects, it becomes a
+/// 400 response explaining the problem.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rejection {
+  message: String,
+}
+
+impl Rejection {
+  pub fn new(message: String) -> Rejection {
+    Rejection { message }
+  }
+
+  pub fn message(&self) -> &str {
+    &self.message
+  }
+
+  /// A 400 response with `{"error": message}` as its body.
+  pub fn into_response(self) -> Response {
+    #[derive(Serialize)]
+    struct Body {
+      error: String,
+    }
+    Json(Body { error: self.message }).with_status(400, "BAD REQUEST")
+  }
+}
+
+impl fmt::Display for Rejection {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.message)
+  }
+}
+
+impl Error for Rejection {}
+
+/// Builds a value out of a request, or explains why it can't.
+pub trait FromRequest: Sized {
+  fn from_request(request: &Request) -> Result<Self, Rejection>;
+}
+
+/// A JSON body deserialized into `T`, or a value serialized as a JSON response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Json<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Json<T> {
+  fn from_request(request: &Request) -> Result<Json<T>, Rejection> {
+    let content_type = request.headers.get("Content-Type").unwrap_or("");
+    let mime = content_type.split(';').next().unwrap().trim();
+    if !mime.eq_ignore_ascii_case("application/json") {
+      return Err(Rejection::new(format!(
+        "expected a Content-Type of application/json, got {:?}",
+        content_type
+      )));
+    }
+
+    serde_json::from_slice(&request.body)
+      .map(Json)
+      .map_err(|e| Rejection::new(format!("invalid JSON body: {}", e)))
+  }
+}
+
+impl<T: Serialize> Json<T> {
+  pub fn into_response(self) -> Response {
+    self.with_status(200, "OK")
+  }
+
+  pub fn with_status(self, status: u16, reason: &str) -> Response {
+    // Serializing our own types only fails for maps with non-string keys
+    let body = serde_json::to_vec(&self.0).expect("value can't be serialized as JSON");
+    let mut response = Response::new(status, reason).with_body(body);
+    response.headers.set("Content-Type", "application/json");
+    response
+  }
+}
+
+/// The parameters of the query string.
+#[derive(Debug, Default)]
+pub struct Query {
+  form: Form,
+}
+
+impl FromRequest for Query {
+  fn from_request(request: &Request) -> Result<Query, Rejection> {
+    let query = match request.target.split_once('?') {
+      Some((_, query)) => query,
+      None => "",
+    };
+    Form::parse_urlencoded(query)
+      .map(|form| Query { form })
+      .map_err(|e| Rejection::new(format!("invalid query string: {}", e)))
+  }
+}
+
+impl Query {
+  /// Parses the parameter into `T`.
+  ///
+  /// # Errors
+  ///
+  /// Returns a rejection if the parameter is missing or doesn't parse.
+  pub fn get<T: FromStr>(&self, name: &str) -> Result<T, Rejection> {
+    self
+      .optional(name)?
+      .ok_or_else(|| Rejection::new(format!("missing query parameter {}", name)))
+  }
+
+  /// Parses the parameter into `T` if it is there.
+  pub fn optional<T: FromStr>(&self, name: &str) -> Result<Option<T>, Rejection> {
+    self
+      .form
+      .text(name)
+      .map(|value| parse(value, || format!("query parameter {}", name)))
+      .transpose()
+  }
+}
+
+/// The segments captured by a route pattern such as `/users/:id`.
+#[derive(Debug, Default, PartialEq)]
+pub struct PathParams {
+  params: Vec<(String, String)>,
+}
+
+impl PathParams {
+  /// Matches the path against the pattern, each `:name` segment of the
+  /// pattern captures the segment at the same place in the path.
+  ///
+  /// Returns `None` if the path doesn't fit the pattern, and a rejection if it
+  /// does but a captured segment isn't properly percent-encoded.
+  pub fn matches(pattern: &str, path: &str) -> Option<Result<PathParams, Rejection>> {
+    let mut patterns = pattern.split('/');
+    let mut segments = path.split('/');
+    let mut captured = Vec::new();
+    loop {
+      match (patterns.next(), segments.next()) {
+        (None, None) => break,
+        (Some(pattern), Some(segment)) => match pattern.strip_prefix(':') {
+          Some(name) if !segment.is_empty() => captured.push((name, segment)),
+          None if pattern == segment => {}
+          _ => return None,
+        },
+        _ => return None,
+      }
+    }
+
+    let params = captured.into_iter().map(|(name, segment)| {
+      let value = form::decode(segment, false).map_err(|e| {
+        let reason = match e {
+          FormError::Malformed(reason) => reason.to_string(),
+          e => e.to_string(),
+        };
+        Rejection::new(format!("invalid path parameter {}: {:?}, {}", name, segment, reason))
+      })?;
+      Ok((name.to_string(), value))
+    });
+    Some(params.collect::<Result<_, _>>().map(|params| PathParams { params }))
+  }
+
+  /// Parses the parameter into `T`.
+  ///
+  /// # Errors
+  ///
+  /// Returns a rejection if the pattern has no such parameter or the segment
+  /// doesn't parse.
+  pub fn get<T: FromStr>(&self, name: &str) -> Result<T, Rejection> {
+    let value = self
+      .params
+      .iter()
+      .find(|(n, _)| n == name)
+      .map(|(_, value)| value)
+      .ok_or_else(|| Rejection::new(format!("missing path parameter {}", name)))?;
+    parse(value, || format!("path parameter {}", name))
+  }
+}
+
+/// Parses the header into `T`.
+///
+/// # Errors
+///
+/// Returns a rejection if the header is missing or doesn't parse.
+pub fn header<T: FromStr>(request: &Request, name: &str) -> Result<T, Rejection> {
+  let value = request
+    .headers
+    .get(name)
+    .ok_or_else(|| Rejection::new(format!("missing header {}", name)))?;
+  parse(value, || format!("header {}", name))
+}
+
+fn parse<T: FromStr, F: FnOnce() -> String>(value: &str, what: F) -> Result<T, Rejection> {
+  value
+    .parse()
+    .map_err(|_| Rejection::new(format!("invalid {}: {:?}", what(), value)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde::Deserialize;
+
+  #[derive(Debug, Deserialize, Serialize, PartialEq)]
+  struct Greeting {
+    name: String,
+    times: u32,
+  }
+
+  fn json_request(body: &str) -> Request {
+    let mut request = Request::new("POST", "/hello");
+    request.headers.set("Content-Type", "application/json; charset=utf-8");
+    request.body = body.as_bytes().to_vec();
+    request
+  }
+
+  #[test]
+  fn json_bodies() {
+    let Json(greeting) = Json::<Greeting>::from_request(&json_request(r#"{"name": "Ferris", "times": 2}"#)).unwrap();
+    assert_eq!(Greeting { name: String::from("Ferris"), times: 2 }, greeting);
+
+    let rejection = Json::<Greeting>::from_request(&json_request(r#"{"name": "Ferris"}"#)).unwrap_err();
+    assert_eq!("invalid JSON body: missing field `times` at line 1 column 18", rejection.message());
+
+    let mut request = json_request("{}");
+    request.headers.set("Content-Type", "text/plain");
+    let rejection = Json::<Greeting>::from_request(&request).unwrap_err();
+    assert_eq!("expected a Content-Type of application/json, got \"text/plain\"", rejection.message());
+  }
+
+  #[test]
+  fn json_responses() {
+    let response = Json(Greeting { name: String::from("Ferris"), times: 1 }).into_response();
+    assert_eq!(200, response.status);
+    assert_eq!(Some("application/json"), response.headers.get("Content-Type"));
+    assert_eq!(br#"{"name":"Ferris","times":1}"#, &response.body[..]);
+
+    let response = Rejection::new(String::from("missing header Accept")).into_response();
+    assert_eq!(400, response.status);
+    assert_eq!(br#"{"error":"missing header Accept"}"#, &response.body[..]);
+  }
+
+  #[test]
+  fn query_strings() {
+    let query = Query::from_request(&Request::new("GET", "/hello?times=3&loud")).unwrap();
+    assert_eq!(3, query.get::<u32>("times").unwrap());
+    assert_eq!(Some(String::new()), query.optional::<String>("loud").unwrap());
+    assert_eq!(None, query.optional::<u32>("missing").unwrap());
+    assert_eq!("missing query parameter missing", query.get::<u32>("missing").unwrap_err().message());
+
+    let query = Query::from_request(&Request::new("GET", "/hello?times=many")).unwrap();
+    assert_eq!("invalid query parameter times: \"many\"", query.get::<u32>("times").unwrap_err().message());
+
+    assert!(Query::from_request(&Request::new("GET", "/hello?times=%")).is_err());
+  }
+
+  #[test]
+  fn path_params() {
+    let params = PathParams::matches("/users/:id/posts/:slug", "/users/42/posts/hello%20world").unwrap().unwrap();
+    assert_eq!(42, params.get::<u64>("id").unwrap());
+    assert_eq!("hello world", params.get::<String>("slug").unwrap());
+    assert_eq!("invalid path parameter slug: \"hello world\"", params.get::<u64>("slug").unwrap_err().message());
+
+    assert_eq!(Some(Ok(PathParams::default())), PathParams::matches("/users", "/users"));
+    assert_eq!(None, PathParams::matches("/users/:id", "/users"));
+    assert_eq!(None, PathParams::matches("/users/:id", "/users/"));
+    assert_eq!(None, PathParams::matches("/users/:id", "/users/1/posts"));
+    assert_eq!(None, PathParams::matches("/users/:id", "/posts/1"));
+  }
+
+  #[test]
+  fn bad_escapes_in_path_params() {
+    let rejection = PathParams::matches("/users/:id", "/users/%zz").unwrap().unwrap_err();
+    assert_eq!("invalid path parameter id: \"%zz\", invalid percent-encoding", rejection.message());
+
+    let rejection = PathParams::matches("/users/:id/posts/:slug", "/users/1/posts/%ff").unwrap().unwrap_err();
+    assert_eq!("invalid path parameter slug: \"%ff\", field is not UTF-8", rejection.message());
+
+    // Only the captured segments are decoded
+    assert_eq!(None, PathParams::matches("/users/:id", "/posts/%zz"));
+  }
+
+  #[test]
+  fn headers() {
+    let mut request = Request::new("GET", "/");
+    request.headers.set("Content-Length", "12");
+    assert_eq!(12, header::<usize>(&request, "content-length").unwrap());
+    assert_eq!("missing header Accept", header::<String>(&request, "Accept").unwrap_err().message());
+
+    request.headers.set("Content-Length", "twelve");
+    assert_eq!("invalid header Content-Length: \"twelve\"", header::<usize>(&request, "Content-Length").unwrap_err().message());
+  }
+}
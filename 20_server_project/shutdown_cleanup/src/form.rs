@@ -0,0 +1,528 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::time::SystemTime;
+
+use crate::http::Request;
+
+const READ_SIZE: usize = 8 * 1024;
+const MAX_LINE: usize = 8 * 1024;
+
+/// Limits protecting the server from huge form posts.
+#[derive(Debug, Clone)]
+pub struct Limits {
+  /// Maximum size of a text field, and of a whole url-encoded body
+  pub max_field_size: usize,
+  /// Maximum size of an uploaded file
+  pub max_file_size: u64,
+  pub max_fields: usize,
+}
+
+impl Default for Limits {
+  fn default() -> Limits {
+    Limits {
+      max_field_size: 64 * 1024,
+      max_file_size: 10 * 1024 * 1024,
+      max_fields: 100,
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum FormError {
+  Io(io::Error),
+  Malformed(&'static str),
+  UnsupportedType(String),
+  /// The field, or the whole body, is over its limit
+  TooLarge(String),
+  TooManyFields,
+  Missing(String),
+  Invalid { name: String, value: String },
+}
+
+impl fmt::Display for FormError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      FormError::Io(e) => write!(f, "could not read the form: {}", e),
+      FormError::Malformed(message) => write!(f, "malformed form: {}", message),
+      FormError::UnsupportedType(content_type) => write!(f, "unsupported form type: {}", content_type),
+      FormError::TooLarge(name) => write!(f, "{} is too large", name),
+      FormError::TooManyFields => write!(f, "too many fields"),
+      FormError::Missing(name) => write!(f, "missing field {}", name),
+      FormError::Invalid { name, value } => write!(f, "invalid value for {}: {}", name, value),
+    }
+  }
+}
+
+impl Error for FormError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    match self {
+      FormError::Io(e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+impl From<io::Error> for FormError {
+  fn from(e: io::Error) -> FormError {
+    FormError::Io(e)
+  }
+}
+
+#[derive(Debug)]
+pub enum Field {
+  Text(String),
+  File(UploadedFile),
+}
+
+/// A file part written to a temporary file, which is deleted when this is
+/// dropped unless it was persisted.
+#[derive(Debug)]
+pub struct UploadedFile {
+  filename: String,
+  content_type: String,
+  path: PathBuf,
+  size: u64,
+}
+
+impl UploadedFile {
+  fn create(filename: String, content_type: String) -> io::Result<(UploadedFile, File)> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let nanos = SystemTime::now()
+      .duration_since(SystemTime::UNIX_EPOCH)
+      .map_or(0, |d| d.subsec_nanos());
+    let path = std::env::temp_dir().join(format!(
+      "upload-{}-{}-{}",
+      std::process::id(),
+      COUNTER.fetch_add(1, Ordering::Relaxed),
+      nanos
+    ));
+    let file = File::options().write(true).create_new(true).open(&path)?;
+    Ok((UploadedFile { filename, content_type, path, size: 0 }, file))
+  }
+
+  /// The name of the file on the client.
+  pub fn filename(&self) -> &str {
+    &self.filename
+  }
+
+  pub fn content_type(&self) -> &str {
+    &self.content_type
+  }
+
+  /// Where the file is stored until it is dropped or persisted.
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  pub fn size(&self) -> u64 {
+    self.size
+  }
+
+  /// Moves the file out of its temporary location, it is kept from then on.
+  pub fn persist<P: AsRef<Path>>(self, to: P) -> io::Result<()> {
+    // Renaming fails across file systems
+    if fs::rename(&self.path, &to).is_err() {
+      fs::copy(&self.path, &to)?;
+    }
+    Ok(())
+  }
+}
+
+impl Drop for UploadedFile {
+  fn drop(&mut self) {
+    let _ = fs::remove_file(&self.path);
+  }
+}
+
+/// The fields of an `application/x-www-form-urlencoded` or
+/// `multipart/form-data` body, in the order they were sent.
+#[derive(Debug, Default)]
+pub struct Form {
+  fields: Vec<(String, Field)>,
+}
+
+impl Form {
+  /// Parses the body of a request that was read in memory.
+  pub fn from_request(request: &Request, limits: &Limits) -> Result<Form, FormError> {
+    Form::read(request, &request.body[..], limits)
+  }
+
+  /// Parses a form body streamed from the reader, the content type comes from
+  /// the request.
+  ///
+  /// # Errors
+  ///
+  /// Returns `UnsupportedType` if the request isn't a form and `TooLarge` or
+  /// `TooManyFields` when the body goes over one of the limits.
+  pub fn read<R: Read>(request: &Request, body: R, limits: &Limits) -> Result<Form, FormError> {
+    let content_type = request.headers.get("Content-Type").unwrap_or("");
+    let mime = content_type.split(';').next().unwrap().trim();
+
+    if mime.eq_ignore_ascii_case("application/x-www-form-urlencoded") {
+      let mut raw = Vec::new();
+      body.take(limits.max_field_size as u64 + 1).read_to_end(&mut raw)?;
+      if raw.len() > limits.max_field_size {
+        return Err(FormError::TooLarge(String::from("form")));
+      }
+      let raw = String::from_utf8(raw).map_err(|_| FormError::Malformed("form is not UTF-8"))?;
+      let form = Form::parse_urlencoded(&raw)?;
+      if form.fields.len() > limits.max_fields {
+        return Err(FormError::TooManyFields);
+      }
+      Ok(form)
+    } else if mime.eq_ignore_ascii_case("multipart/form-data") {
+      let boundary = parameter(content_type, "boundary").ok_or(FormError::Malformed("missing boundary"))?;
+      Form::read_multipart(body, boundary, limits)
+    } else {
+      Err(FormError::UnsupportedType(mime.to_string()))
+    }
+  }
+
+  /// Parses `name=value&other=value`, as sent in bodies and query strings.
+  pub fn parse_urlencoded(input: &str) -> Result<Form, FormError> {
+    let mut form = Form::default();
+    for pair in input.split('&').filter(|pair| !pair.is_empty()) {
+      let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+      form.fields.push((decode(name, true)?, Field::Text(decode(value, true)?)));
+    }
+    Ok(form)
+  }
+
+  /// Parses a multipart body, the file parts are streamed to temporary files.
+  pub fn read_multipart<R: Read>(body: R, boundary: &str, limits: &Limits) -> Result<Form, FormError> {
+    let delimiter = format!("\r\n--{}", boundary).into_bytes();
+    // The first delimiter isn't preceded by a line break
+    let mut parts = Parts { reader: body, buffer: b"\r\n".to_vec(), eof: false };
+    parts.copy_until(&delimiter, &mut io::sink(), limits.max_field_size as u64, "preamble")?;
+
+    let mut form = Form::default();
+    loop {
+      match &parts.take(2)?[..] {
+        b"--" => return Ok(form),
+        b"\r\n" => {}
+        _ => return Err(FormError::Malformed("invalid delimiter")),
+      }
+      if form.fields.len() == limits.max_fields {
+        return Err(FormError::TooManyFields);
+      }
+
+      let mut name = None;
+      let mut filename = None;
+      let mut content_type = String::from("text/plain");
+      loop {
+        let line = parts.read_line()?;
+        if line.is_empty() {
+          break;
+        }
+        let (header, value) = line.split_once(':').ok_or(FormError::Malformed("invalid part header"))?;
+        if header.trim().eq_ignore_ascii_case("Content-Disposition") {
+          name = parameter(value, "name").map(String::from);
+          filename = parameter(value, "filename").map(String::from);
+        } else if header.trim().eq_ignore_ascii_case("Content-Type") {
+          content_type = value.trim().to_string();
+        }
+      }
+      let name = name.ok_or(FormError::Malformed("part without a name"))?;
+
+      let field = match filename {
+        Some(filename) => {
+          // Created first so the file is removed if the upload fails
+          let (mut upload, file) = UploadedFile::create(filename, content_type)?;
+          let mut writer = BufWriter::new(file);
+          upload.size = parts.copy_until(&delimiter, &mut writer, limits.max_file_size, &name)?;
+          writer.flush()?;
+          Field::File(upload)
+        }
+        None => {
+          let mut value = Vec::new();
+          parts.copy_until(&delimiter, &mut value, limits.max_field_size as u64, &name)?;
+          Field::Text(String::from_utf8(value).map_err(|_| FormError::Malformed("field is not UTF-8"))?)
+        }
+      };
+      form.fields.push((name, field));
+    }
+  }
+
+  /// Returns the first text field with this name.
+  pub fn text(&self, name: &str) -> Option<&str> {
+    self.fields.iter().find_map(|(n, field)| match field {
+      Field::Text(value) if n == name => Some(value.as_str()),
+      _ => None,
+    })
+  }
+
+  /// Returns the first file with this name.
+  pub fn file(&self, name: &str) -> Option<&UploadedFile> {
+    self.fields.iter().find_map(|(n, field)| match field {
+      Field::File(file) if n == name => Some(file),
+      _ => None,
+    })
+  }
+
+  /// Removes the first file with this name from the form, to persist it.
+  pub fn take_file(&mut self, name: &str) -> Option<UploadedFile> {
+    let i = self
+      .fields
+      .iter()
+      .position(|(n, field)| n == name && matches!(field, Field::File(_)))?;
+    match self.fields.remove(i).1 {
+      Field::File(file) => Some(file),
+      Field::Text(_) => None,
+    }
+  }
+
+  /// Parses the text field into `T`.
+  ///
+  /// # Errors
+  ///
+  /// Returns `Missing` if there is no such field and `Invalid` if it doesn't
+  /// parse.
+  pub fn value<T: FromStr>(&self, name: &str) -> Result<T, FormError> {
+    let value = self.text(name).ok_or_else(|| FormError::Missing(name.to_string()))?;
+    value.parse().map_err(|_| FormError::Invalid {
+      name: name.to_string(),
+      value: value.to_string(),
+    })
+  }
+
+  pub fn fields(&self) -> impl Iterator<Item = (&str, &Field)> {
+    self.fields.iter().map(|(name, field)| (name.as_str(), field))
+  }
+
+  pub fn len(&self) -> usize {
+    self.fields.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.fields.is_empty()
+  }
+}
+
+// Reads a multipart body through a buffer we control, as a delimiter can be
+// split between two reads
+struct Parts<R> {
+  reader: R,
+  buffer: Vec<u8>,
+  eof: bool,
+}
+
+impl<R: Read> Parts<R> {
+  fn fill(&mut self) -> Result<(), FormError> {
+    if self.eof {
+      return Err(FormError::Malformed("unexpected end of body"));
+    }
+    let start = self.buffer.len();
+    self.buffer.resize(start + READ_SIZE, 0);
+    let read = loop {
+      match self.reader.read(&mut self.buffer[start..]) {
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+        result => break result,
+      }
+    };
+    let read = match read {
+      Ok(read) => read,
+      Err(e) => {
+        self.buffer.truncate(start);
+        return Err(FormError::Io(e));
+      }
+    };
+    self.buffer.truncate(start + read);
+    self.eof = read == 0;
+    Ok(())
+  }
+
+  fn take(&mut self, count: usize) -> Result<Vec<u8>, FormError> {
+    while self.buffer.len() < count {
+      self.fill()?;
+    }
+    Ok(self.buffer.drain(..count).collect())
+  }
+
+  fn read_line(&mut self) -> Result<String, FormError> {
+    loop {
+      if let Some(i) = find(&self.buffer, b"\r\n") {
+        let line: Vec<u8> = self.buffer.drain(..i + 2).take(i).collect();
+        return String::from_utf8(line).map_err(|_| FormError::Malformed("part header is not UTF-8"));
+      }
+      if self.buffer.len() > MAX_LINE {
+        return Err(FormError::Malformed("part header too long"));
+      }
+      self.fill()?;
+    }
+  }
+
+  // Copies everything up to the delimiter, which is skipped, and returns the
+  // number of bytes copied
+  fn copy_until<W: Write>(&mut self, delimiter: &[u8], sink: &mut W, limit: u64, name: &str) -> Result<u64, FormError> {
+    let mut copied = 0;
+    loop {
+      let (end, found) = match find(&self.buffer, delimiter) {
+        Some(i) => (i, true),
+        // The end of the buffer may be the start of the delimiter
+        None => (self.buffer.len().saturating_sub(delimiter.len() - 1), false),
+      };
+      copied += end as u64;
+      if copied > limit {
+        return Err(FormError::TooLarge(name.to_string()));
+      }
+      sink.write_all(&self.buffer[..end])?;
+
+      if found {
+        self.buffer.drain(..end + delimiter.len());
+        return Ok(copied);
+      }
+      self.buffer.drain(..end);
+      self.fill()?;
+    }
+  }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+  haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+// Finds `key=value` or `key="value"` among the `;` separated parameters
+fn parameter<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+  header.split(';').skip(1).find_map(|parameter| {
+    let (k, v) = parameter.split_once('=')?;
+    if k.trim().eq_ignore_ascii_case(key) {
+      Some(v.trim().trim_matches('"'))
+    } else {
+      None
+    }
+  })
+}
+
+// Decodes `%XX` escapes, and `+` as a space in forms and query strings
+pub(crate) fn decode(input: &str, plus_as_space: bool) -> Result<String, FormError> {
+  let bytes = input.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    match bytes[i] {
+      b'+' if plus_as_space => decoded.push(b' '),
+      b'%' => {
+        let hex = bytes.get(i + 1..i + 3).filter(|hex| hex.iter().all(u8::is_ascii_hexdigit));
+        let hex = hex.ok_or(FormError::Malformed("invalid percent-encoding"))?;
+        decoded.push(u8::from_str_radix(std::str::from_utf8(hex).unwrap(), 16).unwrap());
+        i += 2;
+      }
+      byte => decoded.push(byte),
+    }
+    i += 1;
+  }
+  String::from_utf8(decoded).map_err(|_| FormError::Malformed("field is not UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Hands the data out one byte at a time, to split every delimiter
+  struct Trickle<'a>(&'a [u8]);
+
+  impl Read for Trickle<'_> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+      if self.0.is_empty() || buffer.is_empty() {
+        return Ok(0);
+      }
+      buffer[0] = self.0[0];
+      self.0 = &self.0[1..];
+      Ok(1)
+    }
+  }
+
+  fn request(content_type: &str, body: &[u8]) -> Request {
+    let mut request = Request::new("POST", "/form");
+    request.headers.set("Content-Type", content_type);
+    request.body = body.to_vec();
+    request
+  }
+
+  const MULTIPART: &[u8] = b"preamble\r\n--XyZ\r\n\
+Content-Disposition: form-data; name=\"age\"\r\n\r\n\
+42\r\n--XyZ\r\n\
+Content-Disposition: form-data; name=\"upload\"; filename=\"poem.txt\"\r\n\
+Content-Type: text/plain\r\n\r\n\
+I'm nobody!\r\nWho are you?\r\n--XyZ--\r\n";
+
+  #[test]
+  fn urlencoded() {
+    let request = request("application/x-www-form-urlencoded", b"name=Ferris+the+crab&age=7&emoji=%F0%9F%A6%80&empty");
+    let form = Form::from_request(&request, &Limits::default()).unwrap();
+
+    assert_eq!(Some("Ferris the crab"), form.text("name"));
+    assert_eq!(7, form.value::<u8>("age").unwrap());
+    assert_eq!(Some("🦀"), form.text("emoji"));
+    assert_eq!(Some(""), form.text("empty"));
+    assert!(matches!(form.value::<u8>("name"), Err(FormError::Invalid { .. })));
+    assert!(matches!(form.value::<u8>("missing"), Err(FormError::Missing(_))));
+
+    assert!(matches!(Form::parse_urlencoded("a=%4"), Err(FormError::Malformed(_))));
+    assert!(matches!(Form::parse_urlencoded("a=%+1"), Err(FormError::Malformed(_))));
+  }
+
+  #[test]
+  fn multipart() {
+    let request = request("multipart/form-data; boundary=\"XyZ\"", b"");
+    let mut form = Form::read(&request, Trickle(MULTIPART), &Limits::default()).unwrap();
+
+    assert_eq!(2, form.len());
+    assert_eq!(42, form.value::<u32>("age").unwrap());
+    let upload = form.file("upload").unwrap();
+    assert_eq!("poem.txt", upload.filename());
+    assert_eq!("text/plain", upload.content_type());
+    assert_eq!(25, upload.size());
+    assert_eq!("I'm nobody!\r\nWho are you?", fs::read_to_string(upload.path()).unwrap());
+
+    // Temporary files are removed unless persisted
+    let path = upload.path().to_path_buf();
+    let persisted = std::env::temp_dir().join(format!("persisted-{}.txt", std::process::id()));
+    form.take_file("upload").unwrap().persist(&persisted).unwrap();
+    assert!(!path.exists());
+    assert_eq!(25, fs::metadata(&persisted).unwrap().len());
+    fs::remove_file(persisted).unwrap();
+
+    let request = self::request("multipart/form-data; boundary=XyZ", MULTIPART);
+    let form = Form::from_request(&request, &Limits::default()).unwrap();
+    let path = form.file("upload").unwrap().path().to_path_buf();
+    drop(form);
+    assert!(!path.exists());
+  }
+
+  #[test]
+  fn limits() {
+    let request = request("multipart/form-data; boundary=XyZ", MULTIPART);
+    let limits = Limits { max_file_size: 10, ..Limits::default() };
+    assert!(matches!(Form::from_request(&request, &limits), Err(FormError::TooLarge(name)) if name == "upload"));
+
+    let limits = Limits { max_fields: 1, ..Limits::default() };
+    assert!(matches!(Form::from_request(&request, &limits), Err(FormError::TooManyFields)));
+
+    let request = self::request("application/x-www-form-urlencoded", b"name=Ferris");
+    let limits = Limits { max_field_size: 5, ..Limits::default() };
+    assert!(matches!(Form::from_request(&request, &limits), Err(FormError::TooLarge(_))));
+  }
+
+  #[test]
+  fn malformed() {
+    let truncated = request("multipart/form-data; boundary=XyZ", &MULTIPART[..60]);
+    assert!(matches!(Form::from_request(&truncated, &Limits::default()), Err(FormError::Malformed(_))));
+
+    let no_boundary = request("multipart/form-data", MULTIPART);
+    assert!(matches!(Form::from_request(&no_boundary, &Limits::default()), Err(FormError::Malformed(_))));
+
+    let json = request("application/json", b"{}");
+    assert!(matches!(Form::from_request(&json, &Limits::default()), Err(FormError::UnsupportedType(_))));
+  }
+}
@@ -0,0 +1,589 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+
+/// The largest body read in memory, bigger ones are refused before anything is
+/// allocated for them.
+pub const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+/// The longest request, status or header line, with its line break.
+pub const MAX_LINE: usize = 8 * 1024;
+
+/// The most header fields a message can have.
+pub const MAX_HEADERS: usize = 100;
+
+/// HTTP header fields, names are compared case-insensitively.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Headers {
+  fields: Vec<(String, String)>,
+}
+
+impl Headers {
+  pub fn new() -> Headers {
+    Headers { fields: Vec::new() }
+  }
+
+  /// Returns the first value of the header.
+  pub fn get(&self, name: &str) -> Option<&str> {
+    self
+      .fields
+      .iter()
+      .find(|(n, _)| n.eq_ignore_ascii_case(name))
+      .map(|(_, v)| v.as_str())
+  }
+
+  /// Replaces every value of the header with this one.
+  pub fn set(&mut self, name: &str, value: &str) {
+    self.remove(name);
+    self.append(name, value);
+  }
+
+  pub fn append(&mut self, name: &str, value: &str) {
+    self.fields.push((name.to_string(), value.to_string()));
+  }
+
+  pub fn remove(&mut self, name: &str) {
+    self.fields.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+    self.fields.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+  }
+
+  fn read_from<R: BufRead>(reader: &mut R) -> io::Result<Headers> {
+    let mut headers = Headers::new();
+    loop {
+      let line = read_line(reader)?;
+      if line.is_empty() {
+        return Ok(headers);
+      }
+      if headers.fields.len() == MAX_HEADERS {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, HeadTooLarge::Fields));
+      }
+      match line.split_once(':') {
+        Some((name, value)) => headers.append(name.trim(), value.trim()),
+        None => return Err(invalid_data("malformed header")),
+      }
+    }
+  }
+
+  fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+    for (name, value) in self.iter() {
+      write!(writer, "{}: {}\r\n", name, value)?;
+    }
+    writer.write_all(b"\r\n")
+  }
+
+  /// Whether the body is sent as chunks, which takes precedence over a
+  /// Content-Length.
+  pub fn is_chunked(&self) -> bool {
+    self
+      .get("Transfer-Encoding")
+      .is_some_and(|encoding| encoding.to_ascii_lowercase().contains("chunked"))
+  }
+
+  /// Parses the Content-Length header.
+  pub fn content_length(&self) -> io::Result<Option<usize>> {
+    match self.get("Content-Length") {
+      Some(length) => length
+        .parse()
+        .map(Some)
+        .map_err(|_| invalid_data("invalid Content-Length")),
+      None => Ok(None),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+  pub method: String,
+  /// The path with its query string, as sent on the request line
+  pub target: String,
+  pub version: String,
+  pub headers: Headers,
+  pub body: Vec<u8>,
+}
+
+impl Request {
+  pub fn new(method: &str, target: &str) -> Request {
+    Request {
+      method: method.to_string(),
+      target: target.to_string(),
+      version: String::from("HTTP/1.1"),
+      headers: Headers::new(),
+      body: Vec::new(),
+    }
+  }
+
+  /// Reads a request, its body is read only if there is a Content-Length or
+  /// if it is chunked.
+  ///
+  /// # Errors
+  ///
+  /// Returns an `InvalidData` error if the request is malformed and
+  /// `UnexpectedEof` if the connection is closed before the end of it.
+  pub fn read_from<R: BufRead>(reader: &mut R) -> io::Result<Request> {
+    let mut request = Request::read_head_from(reader)?;
+    request.read_body_from(reader)?;
+    Ok(request)
+  }
+
+  /// Reads the request line and the headers only, so the body can be
+  /// streamed from the reader instead.
+  pub fn read_head_from<R: BufRead>(reader: &mut R) -> io::Result<Request> {
+    let line = read_line(reader)?;
+    let mut parts = line.split(' ');
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next()) {
+      (Some(method), Some(target), Some(version)) => (method, target, version),
+      _ => return Err(invalid_data("malformed request line")),
+    };
+    let headers = Headers::read_from(reader)?;
+
+    Ok(Request {
+      method: method.to_string(),
+      target: target.to_string(),
+      version: version.to_string(),
+      headers,
+      body: Vec::new(),
+    })
+  }
+
+  /// Reads the body following a head read with `read_head_from`.
+  ///
+  /// # Errors
+  ///
+  /// Returns an error for which `is_too_large` is true if the body is over
+  /// `MAX_BODY_SIZE`.
+  pub fn read_body_from<R: BufRead>(&mut self, reader: &mut R) -> io::Result<()> {
+    self.body = read_body(reader, &mut self.headers, false)?;
+    Ok(())
+  }
+
+  /// The target without its query string.
+  pub fn path(&self) -> &str {
+    match self.target.split_once('?') {
+      Some((path, _)) => path,
+      None => &self.target,
+    }
+  }
+
+  pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+    self.write_head_to(writer)?;
+    writer.write_all(&self.body)?;
+    writer.flush()
+  }
+
+  /// Writes the request line and the headers, for callers streaming the body.
+  pub fn write_head_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+    write!(writer, "{} {} {}\r\n", self.method, self.target, self.version)?;
+    self.headers.write_to(writer)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+  pub version: String,
+  pub status: u16,
+  pub reason: String,
+  pub headers: Headers,
+  pub body: Vec<u8>,
+}
+
+impl Response {
+  pub fn new(status: u16, reason: &str) -> Response {
+    Response {
+      version: String::from("HTTP/1.1"),
+      status,
+      reason: reason.to_string(),
+      headers: Headers::new(),
+      body: Vec::new(),
+    }
+  }
+
+  /// Replaces the body and updates the Content-Length header.
+  pub fn with_body(mut self, body: Vec<u8>) -> Response {
+    self.headers.set("Content-Length", &body.len().to_string());
+    self.body = body;
+    self
+  }
+
+  /// Reads a response, without a Content-Length or chunks the body goes until
+  /// the connection is closed.
+  ///
+  /// # Errors
+  ///
+  /// Returns an `InvalidData` error if the response is malformed and
+  /// `UnexpectedEof` if the connection is closed before the end of it.
+  pub fn read_from<R: BufRead>(reader: &mut R) -> io::Result<Response> {
+    let line = read_line(reader)?;
+    let mut parts = line.splitn(3, ' ');
+    let (version, status, reason) = match (parts.next(), parts.next()) {
+      (Some(version), Some(status)) => (version, status, parts.next().unwrap_or("")),
+      _ => return Err(invalid_data("malformed status line")),
+    };
+    let status = status.parse().map_err(|_| invalid_data("invalid status code"))?;
+    let mut headers = Headers::read_from(reader)?;
+    let body = match status {
+      100..=199 | 204 | 304 => Vec::new(),
+      _ => read_body(reader, &mut headers, true)?,
+    };
+
+    Ok(Response {
+      version: version.to_string(),
+      status,
+      reason: reason.to_string(),
+      headers,
+      body,
+    })
+  }
+
+  pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+    write!(writer, "{} {} {}\r\n", self.version, self.status, self.reason)?;
+    self.headers.write_to(writer)?;
+    writer.write_all(&self.body)?;
+    writer.flush()
+  }
+}
+
+/// Writes the body as chunks of at most `chunk_size` bytes, followed by the
+/// last empty chunk.
+pub fn write_chunked<R: Read, W: Write>(reader: &mut R, writer: &mut W, chunk_size: usize) -> io::Result<()> {
+  let mut buffer = vec![0; chunk_size];
+  loop {
+    let read = reader.read(&mut buffer)?;
+    write!(writer, "{:x}\r\n", read)?;
+    writer.write_all(&buffer[..read])?;
+    writer.write_all(b"\r\n")?;
+    if read == 0 {
+      return writer.flush();
+    }
+  }
+}
+
+// A chunked body is decoded and its headers updated as if it had been sent
+// with a Content-Length
+fn read_body<R: BufRead>(reader: &mut R, headers: &mut Headers, until_eof: bool) -> io::Result<Vec<u8>> {
+  let mut body = Vec::new();
+  if headers.is_chunked() {
+    read_chunks(reader, &mut body)?;
+    headers.remove("Transfer-Encoding");
+    headers.set("Content-Length", &body.len().to_string());
+  } else if let Some(length) = headers.content_length()? {
+    if length > MAX_BODY_SIZE {
+      return Err(too_large());
+    }
+    // Grows with what actually arrives rather than with what was announced
+    reader.take(length as u64).read_to_end(&mut body)?;
+    if body.len() < length {
+      return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"));
+    }
+  } else if until_eof {
+    reader.take(MAX_BODY_SIZE as u64 + 1).read_to_end(&mut body)?;
+    if body.len() > MAX_BODY_SIZE {
+      return Err(too_large());
+    }
+  }
+  Ok(body)
+}
+
+fn read_chunks<R: BufRead>(reader: &mut R, body: &mut Vec<u8>) -> io::Result<()> {
+  ChunkedReader::new(reader, MAX_BODY_SIZE as u64).read_to_end(body)?;
+  Ok(())
+}
+
+/// Decodes a chunked body as it is read, so it can be streamed rather than
+/// held in memory. Reading stops after the last chunk and its trailer fields,
+/// leaving whatever follows in the reader.
+pub struct ChunkedReader<R> {
+  reader: R,
+  limit: u64,
+  // The sizes announced so far and what is left of the current chunk
+  total: u64,
+  remaining: u64,
+  done: bool,
+}
+
+impl<R: BufRead> ChunkedReader<R> {
+  /// Once the chunk sizes add up to more than `limit`, reading fails with an
+  /// error for which `is_too_large` is true.
+  pub fn new(reader: R, limit: u64) -> ChunkedReader<R> {
+    ChunkedReader { reader, limit, total: 0, remaining: 0, done: false }
+  }
+
+  // Reads up to the data of the next chunk, false after the last one
+  fn next_chunk(&mut self) -> io::Result<bool> {
+    if self.done {
+      return Ok(false);
+    }
+    // The data of the previous chunk ends with a line break
+    if self.total > 0 && !read_line(&mut self.reader)?.is_empty() {
+      return Err(invalid_data("chunk longer than its size"));
+    }
+
+    let line = read_line(&mut self.reader)?;
+    // Chunk extensions come after a semicolon and are ignored
+    let size = line.split(';').next().unwrap().trim();
+    let size = u64::from_str_radix(size, 16).map_err(|_| invalid_data("invalid chunk size"))?;
+    if size == 0 {
+      // Skips the trailer fields
+      while !read_line(&mut self.reader)?.is_empty() {}
+      self.done = true;
+      return Ok(false);
+    }
+
+    // The sizes come from the peer, so their total is checked before reading
+    // rather than trusted
+    self.total = self
+      .total
+      .checked_add(size)
+      .filter(|&total| total <= self.limit)
+      .ok_or_else(too_large)?;
+    self.remaining = size;
+    Ok(true)
+  }
+}
+
+impl<R: BufRead> Read for ChunkedReader<R> {
+  fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+    if buffer.is_empty() || (self.remaining == 0 && !self.next_chunk()?) {
+      return Ok(0);
+    }
+    let wanted = (buffer.len() as u64).min(self.remaining) as usize;
+    let read = self.reader.read(&mut buffer[..wanted])?;
+    if read == 0 {
+      return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"));
+    }
+    self.remaining -= read as u64;
+    Ok(read)
+  }
+}
+
+// Reads a line without its CRLF, at most MAX_LINE bytes of it so a peer
+// can't make us buffer a line that never ends
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<String> {
+  let mut line = String::new();
+  if reader.take(MAX_LINE as u64 + 1).read_line(&mut line)? == 0 {
+    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"));
+  }
+  if line.len() > MAX_LINE {
+    return Err(io::Error::new(io::ErrorKind::InvalidData, HeadTooLarge::Line));
+  }
+  let trimmed = line.trim_end_matches(['\r', '\n']).len();
+  line.truncate(trimmed);
+  Ok(line)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[derive(Debug)]
+struct TooLarge;
+
+impl fmt::Display for TooLarge {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "body larger than {} bytes", MAX_BODY_SIZE)
+  }
+}
+
+impl Error for TooLarge {}
+
+fn too_large() -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, TooLarge)
+}
+
+/// Whether reading a body failed because it is over `MAX_BODY_SIZE`, which
+/// servers answer with 413 Payload Too Large.
+pub fn is_too_large(error: &io::Error) -> bool {
+  error.get_ref().is_some_and(|inner| inner.is::<TooLarge>())
+}
+
+#[derive(Debug)]
+enum HeadTooLarge {
+  Line,
+  Fields,
+}
+
+impl fmt::Display for HeadTooLarge {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      HeadTooLarge::Line => write!(f, "line longer than {} bytes", MAX_LINE),
+      HeadTooLarge::Fields => write!(f, "more than {} header fields", MAX_HEADERS),
+    }
+  }
+}
+
+impl Error for HeadTooLarge {}
+
+/// Whether reading a head failed because a line is over `MAX_LINE` or there
+/// are more than `MAX_HEADERS` fields, which servers answer with 431 Request
+/// Header Fields Too Large.
+pub fn is_head_too_large(error: &io::Error) -> bool {
+  error.get_ref().is_some_and(|inner| inner.is::<HeadTooLarge>())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn read_request() {
+    let raw = b"POST /form?a=1 HTTP/1.1\r\nHost: localhost\r\ncontent-length: 5\r\n\r\nhello";
+    let request = Request::read_from(&mut &raw[..]).unwrap();
+
+    assert_eq!("POST", request.method);
+    assert_eq!("/form", request.path());
+    assert_eq!("/form?a=1", request.target);
+    assert_eq!(Some("localhost"), request.headers.get("HOST"));
+    assert_eq!(b"hello", &request.body[..]);
+  }
+
+  #[test]
+  fn read_request_errors() {
+    let error = Request::read_from(&mut &b"GET\r\n\r\n"[..]).unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidData, error.kind());
+
+    let error = Request::read_from(&mut &b"GET / HTTP/1.1\r\nHost"[..]).unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidData, error.kind());
+
+    let error = Request::read_from(&mut &b""[..]).unwrap_err();
+    assert_eq!(io::ErrorKind::UnexpectedEof, error.kind());
+  }
+
+  #[test]
+  fn lines_over_the_limit() {
+    let mut raw = b"GET / HTTP/1.1\r\nX-Long: ".to_vec();
+    raw.resize(raw.len() + MAX_LINE, b'a');
+    let error = Request::read_from(&mut &raw[..]).unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidData, error.kind());
+    assert!(is_head_too_large(&error));
+    assert_eq!("line longer than 8192 bytes", error.to_string());
+
+    // Right at the limit, the line break included
+    let mut raw = b"GET / HTTP/1.1\r\nX-Long: ".to_vec();
+    raw.resize(raw.len() + MAX_LINE - b"X-Long: \r\n".len(), b'a');
+    raw.extend_from_slice(b"\r\n\r\n");
+    let request = Request::read_from(&mut &raw[..]).unwrap();
+    assert_eq!(MAX_LINE - 10, request.headers.get("X-Long").unwrap().len());
+
+    let mut raw = b"GET /".to_vec();
+    raw.resize(raw.len() + MAX_LINE, b'a');
+    assert!(is_head_too_large(&Request::read_from(&mut &raw[..]).unwrap_err()));
+    assert!(is_head_too_large(&Response::read_from(&mut &raw[..]).unwrap_err()));
+  }
+
+  #[test]
+  fn headers_over_the_limit() {
+    let head = |fields: usize| {
+      let mut raw = String::from("GET / HTTP/1.1\r\n");
+      for i in 0..fields {
+        raw.push_str(&format!("X-Field-{}: {}\r\n", i, i));
+      }
+      raw.push_str("\r\n");
+      raw
+    };
+    let request = Request::read_from(&mut head(MAX_HEADERS).as_bytes()).unwrap();
+    assert_eq!(MAX_HEADERS, request.headers.iter().count());
+
+    let error = Request::read_from(&mut head(MAX_HEADERS + 1).as_bytes()).unwrap_err();
+    assert!(is_head_too_large(&error));
+    assert_eq!("more than 100 header fields", error.to_string());
+    assert!(!is_too_large(&error));
+  }
+
+  #[test]
+  fn response_round_trip() {
+    let mut response = Response::new(404, "NOT FOUND").with_body(b"Oops!".to_vec());
+    response.headers.set("Content-Type", "text/html");
+
+    let mut raw = Vec::new();
+    response.write_to(&mut raw).unwrap();
+    assert_eq!(
+      "HTTP/1.1 404 NOT FOUND\r\nContent-Length: 5\r\nContent-Type: text/html\r\n\r\nOops!",
+      String::from_utf8_lossy(&raw)
+    );
+    assert_eq!(response, Response::read_from(&mut &raw[..]).unwrap());
+  }
+
+  #[test]
+  fn chunked_bodies() {
+    let mut raw = b"POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+    write_chunked(&mut &b"hello world"[..], &mut raw, 4).unwrap();
+    assert!(raw.ends_with(b"4\r\nhell\r\n4\r\no wo\r\n3\r\nrld\r\n0\r\n\r\n"));
+
+    let request = Request::read_from(&mut &raw[..]).unwrap();
+    assert_eq!(b"hello world", &request.body[..]);
+    assert_eq!(None, request.headers.get("Transfer-Encoding"));
+    assert_eq!(Some("11"), request.headers.get("Content-Length"));
+
+    let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5;ext=1\r\nHello\r\n0\r\nExpires: never\r\n\r\n";
+    let response = Response::read_from(&mut &raw[..]).unwrap();
+    assert_eq!(b"Hello", &response.body[..]);
+
+    let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nHello\r\n0\r\n\r\n";
+    let error = Response::read_from(&mut &raw[..]).unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidData, error.kind());
+  }
+
+  #[test]
+  fn streams_chunks() {
+    let mut raw = Vec::new();
+    write_chunked(&mut &b"hello world"[..], &mut raw, 4).unwrap();
+    raw.extend_from_slice(b"GET / HTTP/1.1\r\n\r\n");
+    let mut reader = &raw[..];
+
+    let mut chunks = ChunkedReader::new(&mut reader, 100);
+    let mut buffer = [0; 3];
+    assert_eq!(3, chunks.read(&mut buffer).unwrap());
+    assert_eq!(b"hel", &buffer);
+    let mut rest = String::new();
+    chunks.read_to_string(&mut rest).unwrap();
+    assert_eq!("lo world", rest);
+    assert_eq!(0, chunks.read(&mut buffer).unwrap());
+    // The next request is left for whoever reads after
+    assert_eq!("/", Request::read_from(&mut reader).unwrap().path());
+
+    let mut raw = Vec::new();
+    write_chunked(&mut &b"hello world"[..], &mut raw, 4).unwrap();
+    let error = ChunkedReader::new(&raw[..], 10).read_to_end(&mut Vec::new()).unwrap_err();
+    assert!(is_too_large(&error));
+  }
+
+  #[test]
+  fn bodies_over_the_limit() {
+    let raw = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\nhello", MAX_BODY_SIZE + 1);
+    let error = Request::read_from(&mut raw.as_bytes()).unwrap_err();
+    assert!(is_too_large(&error));
+
+    let raw = format!("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\nhello", usize::MAX);
+    let error = Request::read_from(&mut raw.as_bytes()).unwrap_err();
+    assert!(is_too_large(&error));
+
+    // Chunks adding up past the limit
+    let mut raw = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+    let chunk = vec![b'a'; MAX_BODY_SIZE / 2 + 1];
+    write_chunked(&mut &chunk[..], &mut raw, chunk.len()).unwrap();
+    raw.truncate(raw.len() - 5);
+    write_chunked(&mut &chunk[..], &mut raw, chunk.len()).unwrap();
+    let error = Request::read_from(&mut &raw[..]).unwrap_err();
+    assert!(is_too_large(&error));
+
+    let raw = b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nhello";
+    let error = Request::read_from(&mut &raw[..]).unwrap_err();
+    assert_eq!(io::ErrorKind::UnexpectedEof, error.kind());
+    assert!(!is_too_large(&error));
+  }
+
+  #[test]
+  fn response_without_length_reads_until_closed() {
+    let raw = b"HTTP/1.1 200 OK\r\n\r\n<h1>Hello!</h1>";
+    let response = Response::read_from(&mut &raw[..]).unwrap();
+
+    assert_eq!(200, response.status);
+    assert_eq!(b"<h1>Hello!</h1>", &response.body[..]);
+
+    let raw = b"HTTP/1.1 204 No Content\r\n\r\nnext response";
+    assert!(Response::read_from(&mut &raw[..]).unwrap().body.is_empty());
+  }
+}
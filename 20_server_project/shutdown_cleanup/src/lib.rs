@@ -1,3 +1,12 @@
+pub mod cache;
+pub mod config;
+pub mod extract;
+pub mod form;
+pub mod http;
+pub mod proxy;
+pub mod rate_limit;
+pub mod reload;
+
 use std::thread;
 
 pub struct Worker {
@@ -1,46 +1,235 @@
+use std::fmt::Write as _;
+use std::io;
 use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::SocketAddr;
 use std::net::TcpListener;
 use std::net::TcpStream;
-use std::fs;
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
+use serde::Deserialize;
+use serde::Serialize;
+
+use shutdown_cleanup::extract::FromRequest;
+use shutdown_cleanup::extract::Json;
+use shutdown_cleanup::extract::PathParams;
+use shutdown_cleanup::extract::Query;
+use shutdown_cleanup::extract::Rejection;
+use shutdown_cleanup::form::Field;
+use shutdown_cleanup::form::Form;
+use shutdown_cleanup::form::FormError;
+use shutdown_cleanup::form::Limits;
+use shutdown_cleanup::http;
+use shutdown_cleanup::http::ChunkedReader;
+use shutdown_cleanup::http::Request;
+use shutdown_cleanup::http::Response;
+use shutdown_cleanup::reload::Reloader;
+use shutdown_cleanup::reload::Site;
 use shutdown_cleanup::ThreadPool;
 
+// Settings come from server.conf, editing it applies them without a restart
+
 fn main() {
   let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
   let pool = ThreadPool::new(4);
+  let reloader = Arc::new(Reloader::new("server.conf").unwrap());
+  Reloader::watch(&reloader, Duration::from_secs(1));
 
-  for stream in listener.incoming().take(2) {
+  for (accepted, stream) in listener.incoming().enumerate() {
     let stream = stream.unwrap();
+    // The connection keeps this site until it's done, even if a reload happens
+    let site = reloader.current();
+    let max_connections = site.config.max_connections;
+
+    // Throttled before reaching the pool so one client can't fill its queue
+    match stream.peer_addr() {
+      // The client may already be gone
+      Err(e) => println!("Dropping a connection: {}", e),
+      Ok(peer) => {
+        if let Err(retry_after) = site.limiter.check(peer.ip()) {
+          too_many_requests(stream, retry_after);
+        } else {
+          pool.execute(move || {
+            handle_connection(stream, peer, &site);
+          });
+        }
+      }
+    }
 
-    pool.execute(|| {
-      handle_connection(stream);
-    });
+    if max_connections.is_some_and(|max| accepted + 1 >= max) {
+      break;
+    }
   }
   println!("Shutting down.");
+
+  // Waits for the workers so the statistics include every request
+  drop(pool);
+  let cache = &reloader.current().cache;
+  println!("Cache: {} hits, {} misses.", cache.hits(), cache.misses());
 }
 
-fn handle_connection(mut stream: TcpStream) {
-  let mut buffer = [0; 512];
-  stream.read(&mut buffer).unwrap();
+fn handle_connection(mut stream: TcpStream, peer: SocketAddr, site: &Site) {
+  let mut reader = BufReader::new(&stream);
+  let mut request = match Request::read_head_from(&mut reader) {
+    Ok(request) => request,
+    Err(e) => {
+      println!("Invalid request from {}: {}", peer, e);
+      if http::is_head_too_large(&e) {
+        let response = Response::new(431, "REQUEST HEADER FIELDS TOO LARGE").with_body(e.to_string().into_bytes());
+        let _ = response.write_to(&mut stream);
+      }
+      return;
+    }
+  };
+
+  if request.method == "POST" && request.path() == "/form" {
+    form_response(request, &mut reader).write_to(&mut stream).unwrap();
+    return;
+  }
+  if let Err(e) = request.read_body_from(&mut reader) {
+    println!("Invalid request from {}: {}", peer, e);
+    if http::is_too_large(&e) {
+      let _ = body_too_large().write_to(&mut stream);
+    }
+    return;
+  }
+
+  if let Some(response) = site.proxy.forward(&request, peer) {
+    response.write_to(&mut stream).unwrap();
+    return;
+  }
 
-  let get = b"GET / HTTP/1.1\r\n";
-  let sleep = b"GET /sleep HTTP/1.1\r\n";
+  if let Some(result) = api_response(&request) {
+    let response = result.unwrap_or_else(Rejection::into_response);
+    response.write_to(&mut stream).unwrap();
+    return;
+  }
 
-  let (status_line, filename) = if buffer.starts_with(get) {
-    ("HTTP/1.1 200 OK\r\n\r\n", "hello.html")
-  } else if buffer.starts_with(sleep) {
+  let path = if request.method == "GET" && request.path() == "/sleep" {
     thread::sleep(Duration::from_secs(5));
-    ("HTTP/1.1 200 OK\r\n\r\n", "hello.html")
+    "/"
+  } else if request.method == "GET" {
+    request.path()
   } else {
-    ("HTTP/1.1 404 NOT FOUND\r\n\r\n", "404.html")
+    ""
+  };
+  let (status_line, filename) = match site.config.page(path) {
+    (200, filename) => ("HTTP/1.1 200 OK\r\n\r\n", filename),
+    (_, filename) => ("HTTP/1.1 404 NOT FOUND\r\n\r\n", filename),
   };
 
-  let contents = fs::read_to_string(filename).unwrap();
-
-  let response = format!("{}{}", status_line, contents);
+  // The pages are checked when the config is loaded, but they can still be
+  // removed afterwards
+  let contents = match site.cache.get(&filename) {
+    Ok(contents) => contents,
+    Err(e) => {
+      println!("Could not read {}: {}", filename.display(), e);
+      let response = match e.kind() {
+        io::ErrorKind::NotFound => Response::new(404, "NOT FOUND"),
+        _ => Response::new(500, "INTERNAL SERVER ERROR"),
+      };
+      let _ = response.with_body(Vec::new()).write_to(&mut stream);
+      return;
+    }
+  };
 
-  stream.write(response.as_bytes()).unwrap();
+  stream.write_all(status_line.as_bytes()).unwrap();
+  stream.write_all(&contents).unwrap();
   stream.flush().unwrap();
-}
\ No newline at end of file
+}
+
+#[derive(Deserialize)]
+struct Greeting {
+  name: String,
+  #[serde(default = "once")]
+  times: usize,
+}
+
+fn once() -> usize {
+  1
+}
+
+#[derive(Serialize)]
+struct Message {
+  message: String,
+}
+
+// GET /hello/Ferris?times=2 and POST /hello with {"name": "Ferris", "times": 2}
+fn api_response(request: &Request) -> Option<Result<Response, Rejection>> {
+  let greeting = match request.method.as_str() {
+    "GET" => {
+      let params = PathParams::matches("/hello/:name", request.path())?;
+      params.and_then(|params| {
+        let query = Query::from_request(request)?;
+        Ok(Greeting {
+          name: params.get("name")?,
+          times: query.optional("times")?.unwrap_or(1),
+        })
+      })
+    }
+    "POST" if request.path() == "/hello" => Json::from_request(request).map(|Json(greeting)| greeting),
+    _ => return None,
+  };
+
+  Some(greeting.and_then(|greeting| {
+    if greeting.times > 10 {
+      return Err(Rejection::new(String::from("times must be at most 10")));
+    }
+    let message = vec![format!("Hello, {}!", greeting.name); greeting.times].join(" ");
+    Ok(Json(Message { message }).into_response())
+  }))
+}
+
+// Lists the fields that were posted
+fn form_response<R: BufRead>(request: Request, reader: &mut R) -> Response {
+  let limits = Limits::default();
+  // Streamed from the connection so uploaded files go straight to disk, the
+  // limits bound what is kept and MAX_BODY_SIZE the whole body
+  let form = if request.headers.is_chunked() {
+    Form::read(&request, ChunkedReader::new(reader, http::MAX_BODY_SIZE as u64), &limits)
+  } else {
+    match request.headers.content_length() {
+      Ok(Some(length)) if length > http::MAX_BODY_SIZE => return body_too_large(),
+      Ok(length) => Form::read(&request, reader.take(length.unwrap_or(0) as u64), &limits),
+      Err(e) => Err(FormError::Io(e)),
+    }
+  };
+
+  match form {
+    Ok(form) => {
+      let mut body = String::new();
+      for (name, field) in form.fields() {
+        match field {
+          Field::Text(value) => writeln!(body, "{} = {}", name, value).unwrap(),
+          Field::File(file) => writeln!(body, "{} = {} ({} bytes)", name, file.filename(), file.size()).unwrap(),
+        }
+      }
+      Response::new(200, "OK").with_body(body.into_bytes())
+    }
+    Err(FormError::TooLarge(name)) => {
+      Response::new(413, "PAYLOAD TOO LARGE").with_body(format!("{} is too large", name).into_bytes())
+    }
+    Err(FormError::Io(e)) if http::is_too_large(&e) => body_too_large(),
+    Err(e) => Response::new(400, "BAD REQUEST").with_body(e.to_string().into_bytes()),
+  }
+}
+
+fn body_too_large() -> Response {
+  let message = format!("body larger than {} bytes", http::MAX_BODY_SIZE);
+  Response::new(413, "PAYLOAD TOO LARGE").with_body(message.into_bytes())
+}
+
+fn too_many_requests(mut stream: TcpStream, retry_after: Duration) {
+  // Reads what the client already sent without waiting, otherwise closing
+  // the connection with unread data resets it before the response arrives
+  stream.set_nonblocking(true).unwrap();
+  let _ = stream.read(&mut [0; 1024]);
+  stream.set_nonblocking(false).unwrap();
+
+  let mut response = Response::new(429, "TOO MANY REQUESTS");
+  let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+  response.headers.set("Retry-After", &seconds.to_string());
+  let _ = response.with_body(Vec::new()).write_to(&mut stream);
+}
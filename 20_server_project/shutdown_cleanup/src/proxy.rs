@@ -0,0 +1,273 @@
+use std::error::Error;
+use std::io;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::net::TcpStream;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::http::Request;
+use crate::http::Response;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Forwards the requests matching a path prefix to upstream servers.
+///
+/// Each prefix round-robins over its upstreams and skips the ones that failed
+/// their last health check or connection attempt.
+#[derive(Default)]
+pub struct Proxy {
+  routes: Vec<Route>,
+}
+
+struct Route {
+  prefix: String,
+  upstreams: Vec<Upstream>,
+  next: AtomicUsize,
+}
+
+struct Upstream {
+  addr: SocketAddr,
+  healthy: AtomicBool,
+}
+
+impl Route {
+  fn matches(&self, path: &str) -> bool {
+    match path.strip_prefix(self.prefix.as_str()) {
+      Some(rest) => self.prefix.ends_with('/') || rest.is_empty() || rest.starts_with('/'),
+      None => false,
+    }
+  }
+}
+
+impl Proxy {
+  pub fn new() -> Proxy {
+    Proxy { routes: Vec::new() }
+  }
+
+  /// Parses routes written as `/prefix=host:port,host:port`, separated by `;`.
+  ///
+  /// # Errors
+  ///
+  /// Returns an error if a route has no `=` or an address is invalid.
+  pub fn parse(spec: &str) -> Result<Proxy, Box<dyn Error>> {
+    let mut proxy = Proxy::new();
+    for route in spec.split(';').map(str::trim).filter(|r| !r.is_empty()) {
+      let (prefix, upstreams) = route
+        .split_once('=')
+        .ok_or_else(|| format!("route without upstreams: {}", route))?;
+      let upstreams = upstreams
+        .split(',')
+        .map(|addr| addr.trim().parse())
+        .collect::<Result<Vec<SocketAddr>, _>>()?;
+      proxy = proxy.route(prefix.trim(), &upstreams);
+    }
+    Ok(proxy)
+  }
+
+  /// Adds a route, when prefixes overlap the longest one wins.
+  ///
+  /// # Panics
+  ///
+  /// The `route` function will panic if there are no upstreams.
+  pub fn route(mut self, prefix: &str, upstreams: &[SocketAddr]) -> Proxy {
+    assert!(!upstreams.is_empty());
+    self.routes.push(Route {
+      prefix: prefix.to_string(),
+      upstreams: upstreams
+        .iter()
+        .map(|&addr| Upstream { addr, healthy: AtomicBool::new(true) })
+        .collect(),
+      next: AtomicUsize::new(0),
+    });
+    self.routes.sort_by_key(|r| std::cmp::Reverse(r.prefix.len()));
+    self
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.routes.is_empty()
+  }
+
+  /// Forwards the request to the next healthy upstream of the matching route.
+  ///
+  /// Returns `None` if no route matches the path and a 502 response if every
+  /// upstream is down.
+  pub fn forward(&self, request: &Request, client: SocketAddr) -> Option<Response> {
+    let route = self.routes.iter().find(|r| r.matches(request.path()))?;
+
+    let mut request = request.clone();
+    let forwarded_for = match request.headers.get("X-Forwarded-For") {
+      Some(previous) => format!("{}, {}", previous, client.ip()),
+      None => client.ip().to_string(),
+    };
+    request.headers.set("X-Forwarded-For", &forwarded_for);
+    // We read the upstream response until the connection is closed
+    request.headers.set("Connection", "close");
+
+    let count = route.upstreams.len();
+    let start = route.next.fetch_add(1, Ordering::Relaxed);
+    for i in 0..count {
+      let upstream = &route.upstreams[(start + i) % count];
+      if !upstream.healthy.load(Ordering::Relaxed) {
+        continue;
+      }
+      request.headers.set("Host", &upstream.addr.to_string());
+      match send(upstream.addr, &request) {
+        Ok(mut response) => {
+          response.headers.remove("Connection");
+          response.headers.set("Content-Length", &response.body.len().to_string());
+          return Some(response);
+        }
+        Err(e) => {
+          println!("Upstream {} failed: {}", upstream.addr, e);
+          upstream.healthy.store(false, Ordering::Relaxed);
+        }
+      }
+    }
+
+    Some(Response::new(502, "BAD GATEWAY").with_body(b"No upstream available".to_vec()))
+  }
+
+  /// Marks each upstream healthy or not depending on whether it accepts connections.
+  pub fn check_health(&self) {
+    for upstream in self.routes.iter().flat_map(|r| &r.upstreams) {
+      let healthy = TcpStream::connect_timeout(&upstream.addr, CONNECT_TIMEOUT).is_ok();
+      if healthy != upstream.healthy.swap(healthy, Ordering::Relaxed) {
+        println!("Upstream {} is {}.", upstream.addr, if healthy { "up" } else { "down" });
+      }
+    }
+  }
+
+  /// Checks the upstreams every `interval` on a background thread, which
+  /// stops once the proxy is dropped.
+  pub fn spawn_health_checks(proxy: &Arc<Proxy>, interval: Duration) -> thread::JoinHandle<()> {
+    let proxy = Arc::downgrade(proxy);
+    thread::spawn(move || loop {
+      thread::sleep(interval);
+      match proxy.upgrade() {
+        Some(proxy) => proxy.check_health(),
+        None => break,
+      }
+    })
+  }
+}
+
+fn send(addr: SocketAddr, request: &Request) -> io::Result<Response> {
+  let mut stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+  stream.set_read_timeout(Some(READ_TIMEOUT))?;
+  request.write_to(&mut stream)?;
+  Response::read_from(&mut BufReader::new(stream))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::net::TcpListener;
+
+  // Answers every request with its name and the headers the proxy rewrote
+  fn backend(name: &'static str) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        let mut stream = stream.unwrap();
+        let request = match Request::read_from(&mut BufReader::new(&stream)) {
+          Ok(request) => request,
+          // Health checks connect without sending anything
+          Err(_) => continue,
+        };
+        let body = format!(
+          "{} {} {} {}",
+          name,
+          request.target,
+          request.headers.get("Host").unwrap(),
+          request.headers.get("X-Forwarded-For").unwrap()
+        );
+        Response::new(200, "OK").with_body(body.into_bytes()).write_to(&mut stream).unwrap();
+      }
+    });
+    addr
+  }
+
+  // An address nothing listens on anymore
+  fn closed() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+  }
+
+  fn client() -> SocketAddr {
+    "10.0.0.1:5000".parse().unwrap()
+  }
+
+  fn body(response: Response) -> String {
+    String::from_utf8(response.body).unwrap()
+  }
+
+  #[test]
+  fn matches_prefixes_on_segment_boundaries() {
+    let proxy = Proxy::new().route("/api", &[closed()]);
+    assert!(proxy.forward(&Request::new("GET", "/"), client()).is_none());
+    assert!(proxy.forward(&Request::new("GET", "/apis"), client()).is_none());
+    assert!(proxy.forward(&Request::new("GET", "/api"), client()).is_some());
+    assert!(proxy.forward(&Request::new("GET", "/api/users?id=1"), client()).is_some());
+  }
+
+  #[test]
+  fn round_robins_and_rewrites_headers() {
+    let (a, b) = (backend("a"), backend("b"));
+    let proxy = Proxy::new().route("/", &[closed()]).route("/api", &[a, b]);
+
+    let mut request = Request::new("GET", "/api/users");
+    request.headers.set("Host", "example.com");
+    let bodies: Vec<String> = (0..4)
+      .map(|_| body(proxy.forward(&request, client()).unwrap()))
+      .collect();
+    assert_eq!(format!("a /api/users {} 10.0.0.1", a), bodies[0]);
+    assert_eq!(format!("b /api/users {} 10.0.0.1", b), bodies[1]);
+    assert_eq!(bodies[0], bodies[2]);
+    assert_eq!(bodies[1], bodies[3]);
+
+    request.headers.set("X-Forwarded-For", "192.168.1.1");
+    let response = proxy.forward(&request, client()).unwrap();
+    assert_eq!(format!("a /api/users {} 192.168.1.1, 10.0.0.1", a), body(response));
+  }
+
+  #[test]
+  fn skips_unhealthy_upstreams() {
+    let (down, up) = (closed(), backend("up"));
+    let proxy = Proxy::new().route("/", &[down, up]);
+
+    // The first request fails over to the next upstream
+    for _ in 0..3 {
+      let response = proxy.forward(&Request::new("GET", "/"), client()).unwrap();
+      assert_eq!(200, response.status);
+      assert!(body(response).starts_with("up "));
+    }
+
+    proxy.check_health();
+    assert!(!proxy.routes[0].upstreams[0].healthy.load(Ordering::Relaxed));
+    assert!(proxy.routes[0].upstreams[1].healthy.load(Ordering::Relaxed));
+  }
+
+  #[test]
+  fn bad_gateway_without_upstreams() {
+    let proxy = Proxy::new().route("/", &[closed(), closed()]);
+    let response = proxy.forward(&Request::new("GET", "/"), client()).unwrap();
+    assert_eq!(502, response.status);
+  }
+
+  #[test]
+  fn parse_routes() {
+    let proxy = Proxy::parse("/api=127.0.0.1:9000, 127.0.0.1:9001; /admin=127.0.0.1:9100").unwrap();
+    assert_eq!("/admin", proxy.routes[0].prefix);
+    assert_eq!(2, proxy.routes[1].upstreams.len());
+
+    assert!(Proxy::parse("/api").is_err());
+    assert!(Proxy::parse("/api=localhost").is_err());
+    assert!(Proxy::parse("").unwrap().is_empty());
+  }
+}
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+/// A token bucket per client address.
+///
+/// Each bucket holds up to `burst` tokens and gets `refill` tokens back per
+/// second, a request takes one token and is refused when the bucket is empty.
+pub struct RateLimiter {
+  burst: f64,
+  refill: f64,
+  buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+struct Bucket {
+  tokens: f64,
+  updated: Instant,
+}
+
+impl RateLimiter {
+  /// Create a new RateLimiter.
+  ///
+  /// # Panics
+  ///
+  /// The `new` function will panic if the burst or the refill is zero.
+  pub fn new(burst: u32, refill: f64) -> RateLimiter {
+    assert!(burst > 0);
+    assert!(refill > 0.0);
+    RateLimiter {
+      burst: f64::from(burst),
+      refill,
+      buckets: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /// Takes a token from the client's bucket.
+  ///
+  /// # Errors
+  ///
+  /// Returns how long the client has to wait for its next token when the
+  /// bucket is empty.
+  pub fn check(&self, client: IpAddr) -> Result<(), Duration> {
+    self.check_at(client, Instant::now())
+  }
+
+  fn check_at(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+    let mut buckets = self.buckets.lock().unwrap();
+    let bucket = buckets.entry(client).or_insert(Bucket { tokens: self.burst, updated: now });
+
+    let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * self.refill).min(self.burst);
+    bucket.updated = now;
+
+    if bucket.tokens >= 1.0 {
+      bucket.tokens -= 1.0;
+      Ok(())
+    } else {
+      Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.refill))
+    }
+  }
+
+  /// Forgets the clients that haven't made a request for `idle`.
+  pub fn remove_idle(&self, idle: Duration) {
+    self.remove_idle_at(idle, Instant::now());
+  }
+
+  fn remove_idle_at(&self, idle: Duration, now: Instant) {
+    let mut buckets = self.buckets.lock().unwrap();
+    buckets.retain(|_, bucket| now.saturating_duration_since(bucket.updated) < idle);
+  }
+
+  /// Number of clients currently tracked.
+  pub fn len(&self) -> usize {
+    self.buckets.lock().unwrap().len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Removes the idle clients every `idle` on a background thread, which
+  /// stops once the limiter is dropped.
+  pub fn spawn_cleanup(limiter: &Arc<RateLimiter>, idle: Duration) -> thread::JoinHandle<()> {
+    let limiter = Arc::downgrade(limiter);
+    thread::spawn(move || loop {
+      thread::sleep(idle);
+      match limiter.upgrade() {
+        Some(limiter) => limiter.remove_idle(idle),
+        None => break,
+      }
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn ip(last: u8) -> IpAddr {
+    IpAddr::from([127, 0, 0, last])
+  }
+
+  #[test]
+  fn allows_bursts_then_throttles() {
+    let limiter = RateLimiter::new(3, 1.0);
+    let now = Instant::now();
+
+    for _ in 0..3 {
+      assert_eq!(Ok(()), limiter.check_at(ip(1), now));
+    }
+    assert_eq!(Err(Duration::from_secs(1)), limiter.check_at(ip(1), now));
+    // Other clients have their own bucket
+    assert_eq!(Ok(()), limiter.check_at(ip(2), now));
+  }
+
+  #[test]
+  fn refills_over_time() {
+    let limiter = RateLimiter::new(2, 4.0);
+    let now = Instant::now();
+
+    limiter.check_at(ip(1), now).unwrap();
+    limiter.check_at(ip(1), now).unwrap();
+    let retry_after = limiter.check_at(ip(1), now).unwrap_err();
+    assert_eq!(Duration::from_millis(250), retry_after);
+
+    assert_eq!(Ok(()), limiter.check_at(ip(1), now + retry_after));
+    assert!(limiter.check_at(ip(1), now + retry_after).is_err());
+
+    // The bucket never holds more than the burst
+    let later = now + Duration::from_secs(60);
+    limiter.check_at(ip(1), later).unwrap();
+    limiter.check_at(ip(1), later).unwrap();
+    assert!(limiter.check_at(ip(1), later).is_err());
+  }
+
+  #[test]
+  fn removes_idle_clients() {
+    let limiter = RateLimiter::new(1, 1.0);
+    let now = Instant::now();
+
+    limiter.check_at(ip(1), now).unwrap();
+    limiter.check_at(ip(2), now + Duration::from_secs(5)).unwrap();
+    assert_eq!(2, limiter.len());
+
+    limiter.remove_idle_at(Duration::from_secs(10), now + Duration::from_secs(12));
+    assert_eq!(1, limiter.len());
+    limiter.remove_idle_at(Duration::from_secs(10), now + Duration::from_secs(15));
+    assert!(limiter.is_empty());
+  }
+}
@@ -0,0 +1,220 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::thread;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use crate::cache::FileCache;
+use crate::config::Config;
+use crate::proxy::Proxy;
+use crate::rate_limit::RateLimiter;
+
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+const IDLE_CLIENT: Duration = Duration::from_secs(60);
+
+/// Everything the server needs to answer requests, built from one version of
+/// the config.
+pub struct Site {
+  pub config: Config,
+  pub cache: Arc<FileCache>,
+  pub proxy: Arc<Proxy>,
+  pub limiter: Arc<RateLimiter>,
+}
+
+impl Site {
+  /// Builds the site, the parts whose settings didn't change since the
+  /// previous site are shared with it so the cache stays warm.
+  ///
+  /// # Errors
+  ///
+  /// Returns an error if a page can't be read or the upstreams can't be
+  /// parsed.
+  pub fn new(config: Config, previous: Option<&Site>) -> Result<Site, Box<dyn Error>> {
+    config.check_pages()?;
+    let cache = match previous {
+      Some(site) if site.config.cache_size == config.cache_size => Arc::clone(&site.cache),
+      _ => Arc::new(FileCache::new(config.cache_size)),
+    };
+
+    let proxy = match previous {
+      Some(site) if site.config.upstreams == config.upstreams => Arc::clone(&site.proxy),
+      _ => {
+        let proxy = Arc::new(Proxy::parse(&config.upstreams)?);
+        // Stops when the last site using this proxy is dropped
+        Proxy::spawn_health_checks(&proxy, HEALTH_CHECK_INTERVAL);
+        proxy
+      }
+    };
+
+    let limiter = match previous {
+      Some(site)
+        if site.config.rate_limit_burst == config.rate_limit_burst
+          && site.config.rate_limit_refill == config.rate_limit_refill =>
+      {
+        Arc::clone(&site.limiter)
+      }
+      _ => {
+        let limiter = Arc::new(RateLimiter::new(config.rate_limit_burst, config.rate_limit_refill));
+        RateLimiter::spawn_cleanup(&limiter, IDLE_CLIENT);
+        limiter
+      }
+    };
+
+    Ok(Site { config, cache, proxy, limiter })
+  }
+}
+
+/// Holds the current site and replaces it when the config file changes.
+///
+/// Connections keep the `Arc<Site>` they started with, so a reload never
+/// changes the settings of a request halfway through.
+pub struct Reloader {
+  path: PathBuf,
+  current: RwLock<Arc<Site>>,
+}
+
+impl Reloader {
+  pub fn new<P: AsRef<Path>>(path: P) -> Result<Reloader, Box<dyn Error>> {
+    let site = Site::new(Config::load(&path)?, None)?;
+    Ok(Reloader {
+      path: path.as_ref().to_path_buf(),
+      current: RwLock::new(Arc::new(site)),
+    })
+  }
+
+  pub fn current(&self) -> Arc<Site> {
+    Arc::clone(&self.current.read().unwrap())
+  }
+
+  /// Reads the config file again and swaps the site.
+  ///
+  /// # Errors
+  ///
+  /// Returns an error if the new config is invalid, the current site is kept
+  /// in that case.
+  pub fn reload(&self) -> Result<(), Box<dyn Error>> {
+    let config = Config::load(&self.path)?;
+    let site = Site::new(config, Some(&self.current()))?;
+    *self.current.write().unwrap() = Arc::new(site);
+    Ok(())
+  }
+
+  /// Reloads every time the modification time of the config file changes,
+  /// checking every `interval` on a background thread which stops once the
+  /// reloader is dropped.
+  pub fn watch(reloader: &Arc<Reloader>, interval: Duration) -> thread::JoinHandle<()> {
+    let reloader = Arc::downgrade(reloader);
+    thread::spawn(move || {
+      let mut last_modified = None;
+      while let Some(reloader) = reloader.upgrade() {
+        let modified = modified(&reloader.path);
+        if last_modified.is_some() && modified != last_modified {
+          match reloader.reload() {
+            Ok(()) => println!("Reloaded {}.", reloader.path.display()),
+            Err(e) => println!("Keeping the previous config, {} is invalid: {}", reloader.path.display(), e),
+          }
+        }
+        last_modified = modified;
+
+        drop(reloader);
+        thread::sleep(interval);
+      }
+    })
+  }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+  fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs::File;
+
+  fn config_file(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("shutdown_cleanup_{}_{}.conf", name, std::process::id()));
+    fs::write(&path, contents).unwrap();
+    path
+  }
+
+  // A root with the pages the configs below refer to
+  fn pages(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("shutdown_cleanup_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&root).unwrap();
+    for page in &["hello.html", "index.html", "404.html"] {
+      fs::write(root.join(page), page).unwrap();
+    }
+    root
+  }
+
+  #[test]
+  fn reload_keeps_previous_snapshots() {
+    let root = pages("reload");
+    let path = config_file("reload", "route / = hello.html\ncache_size = 100");
+    let reloader = Reloader::new(&path).unwrap();
+    let before = reloader.current();
+
+    let contents = format!("root = {}\nroute / = index.html\ncache_size = 100\nrate_limit_burst = 5", root.display());
+    fs::write(&path, contents).unwrap();
+    reloader.reload().unwrap();
+    let after = reloader.current();
+
+    // A connection holding the old site still sees the old settings
+    assert_eq!(PathBuf::from("./hello.html"), before.config.page("/").1);
+    assert_eq!(root.join("index.html"), after.config.page("/").1);
+    assert!(Arc::ptr_eq(&before.cache, &after.cache));
+    assert!(Arc::ptr_eq(&before.proxy, &after.proxy));
+    assert!(!Arc::ptr_eq(&before.limiter, &after.limiter));
+
+    fs::remove_file(path).unwrap();
+    fs::remove_dir_all(root).unwrap();
+  }
+
+  #[test]
+  fn invalid_config_keeps_current_site() {
+    let path = config_file("invalid", "root = .");
+    let reloader = Reloader::new(&path).unwrap();
+
+    fs::write(&path, "root = .\nupstreams = /api=nowhere").unwrap();
+    assert!(reloader.reload().is_err());
+
+    // Serving a page that isn't there would fail every request
+    fs::write(&path, "root = .\nroute / = missing.html").unwrap();
+    let error = reloader.reload().unwrap_err();
+    assert!(error.to_string().starts_with("can't read ./missing.html"));
+    assert_eq!(PathBuf::from("./hello.html"), reloader.current().config.page("/").1);
+
+    fs::remove_file(path).unwrap();
+    assert!(Reloader::new("no such file").is_err());
+  }
+
+  #[test]
+  fn watch_reloads_on_change() {
+    let (before, after) = (pages("before"), pages("after"));
+    let path = config_file("watch", &format!("root = {}", before.display()));
+    let reloader = Arc::new(Reloader::new(&path).unwrap());
+    Reloader::watch(&reloader, Duration::from_millis(10));
+    thread::sleep(Duration::from_millis(50));
+
+    fs::write(&path, format!("root = {}", after.display())).unwrap();
+    let later = SystemTime::now() + Duration::from_secs(10);
+    File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+
+    for _ in 0..100 {
+      if reloader.current().config.root == after {
+        break;
+      }
+      thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(after, reloader.current().config.root);
+
+    fs::remove_file(path).unwrap();
+    fs::remove_dir_all(before).unwrap();
+    fs::remove_dir_all(after).unwrap();
+  }
+}
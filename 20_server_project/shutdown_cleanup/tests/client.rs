@@ -0,0 +1,86 @@
+use std::fs;
+use std::net::TcpStream;
+use std::path::Path;
+use std::process::Child;
+use std::process::Command;
+use std::process::Stdio;
+use std::thread;
+use std::time::Duration;
+
+use client::Client;
+
+// The server binary, stopped when dropped
+struct Server(Child);
+
+impl Drop for Server {
+  fn drop(&mut self) {
+    let _ = self.0.kill();
+    let _ = self.0.wait();
+  }
+}
+
+// Runs the server with the pages of the crate, it listens on 127.0.0.1:7878
+fn server() -> Server {
+  let dir = std::env::temp_dir().join(format!("shutdown_cleanup_client_{}", std::process::id()));
+  fs::create_dir_all(&dir).unwrap();
+  let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+  let config = format!("root = {}\nroute / = hello.html\nnot_found = 404.html\n", root.display());
+  fs::write(dir.join("server.conf"), config).unwrap();
+
+  let child = Command::new(env!("CARGO_BIN_EXE_shutdown_cleanup"))
+    .current_dir(&dir)
+    .stdout(Stdio::null())
+    .spawn()
+    .unwrap();
+  let server = Server(child);
+  for _ in 0..50 {
+    if TcpStream::connect("127.0.0.1:7878").is_ok() {
+      return server;
+    }
+    thread::sleep(Duration::from_millis(100));
+  }
+  panic!("the server didn't start");
+}
+
+#[test]
+fn client_talks_to_the_server() {
+  let _server = server();
+  let client = Client::new();
+
+  // The pages have no Content-Length, they end when the server closes
+  let response = client.get("http://127.0.0.1:7878/").send().unwrap();
+  assert_eq!(200, response.status);
+  assert_eq!(fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("hello.html")).unwrap(), response.body);
+  assert_eq!(404, client.get("http://127.0.0.1:7878/frog").send().unwrap().status);
+
+  let response = client.get("http://127.0.0.1:7878/hello/Fe%72ris?times=2").send().unwrap();
+  assert_eq!(Some("application/json"), response.headers.get("Content-Type"));
+  assert_eq!(br#"{"message":"Hello, Ferris! Hello, Ferris!"}"#, &response.body[..]);
+  assert_eq!(400, client.get("http://127.0.0.1:7878/hello/%zz").send().unwrap().status);
+
+  // The server closes every connection, the POSTs go on new ones rather
+  // than being sent twice
+  for _ in 0..2 {
+    let response = client
+      .post("http://127.0.0.1:7878/hello")
+      .header("Content-Type", "application/json")
+      .body(r#"{"name": "Ferris"}"#)
+      .send()
+      .unwrap();
+    assert_eq!(br#"{"message":"Hello, Ferris!"}"#, &response.body[..]);
+  }
+
+  let form = "a=1&b=2".as_bytes();
+  let requests = [
+    client.post("http://127.0.0.1:7878/form").body(form),
+    client.post("http://127.0.0.1:7878/form").body_reader(form),
+  ];
+  for request in requests {
+    let response = request
+      .header("Content-Type", "application/x-www-form-urlencoded")
+      .send()
+      .unwrap();
+    assert_eq!(200, response.status);
+    assert_eq!("a = 1\nb = 2\n", String::from_utf8(response.body).unwrap());
+  }
+}